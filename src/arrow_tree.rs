@@ -0,0 +1,494 @@
+//! A quadtree over the stream arrows
+//!
+//! Groups of arrows that are far away compared to their size
+//! are summed up as one arrow at their center,
+//! so computing the stream at a position does not visit every arrow.
+
+use kernel::{ Falloff, Kernel };
+use stream::Arrow;
+
+/// The deepest a node can be, which stops splitting arrows at the same spot.
+const MAX_DEPTH: usize = 16;
+
+#[derive(Clone)]
+pub struct Node {
+    /// The center of the square covered by the node.
+    pub center: [f64; 2],
+    /// Half the side of the square covered by the node.
+    pub half_size: f64,
+    /// The average position of the arrows below.
+    pub centroid: [f64; 2],
+    /// The number of arrows below.
+    pub count: usize,
+    /// The directions of the arrows below, scaled by their factor.
+    pub dir_sum: [f64; 2],
+    /// The part of the arrow stream functions below that does not
+    /// depend on the position, see `ArrowTree::curl`.
+    pub psi_offset: f64,
+    /// The first of four children stored next to each other, `None` for a leaf.
+    pub children: Option<usize>,
+    /// The arrows of a leaf.
+    pub arrows: Vec<usize>,
+}
+
+/// An arrow or a node summed up by `ArrowTree::gather`.
+#[derive(Copy, Clone)]
+enum Part {
+    Arrow(usize),
+    Node(usize),
+}
+
+/// Children are always stored after their parent.
+#[derive(Clone)]
+pub struct ArrowTree {
+    pub nodes: Vec<Node>,
+    /// How much each arrow counts right now, see `Oscillation::factor`.
+    pub factors: Vec<f64>,
+}
+
+impl ArrowTree {
+    /// Builds a tree over arrow positions.
+    pub fn new(arrows: &[Arrow]) -> ArrowTree {
+        use settings::stream::TREE_LEAF_SIZE;
+
+        let mut tree = ArrowTree { nodes: Vec::new(), factors: Vec::new() };
+        if arrows.len() == 0 { return tree; }
+
+        let mut min = arrows[0].pos;
+        let mut max = arrows[0].pos;
+        for arrow in arrows.iter() {
+            for k in 0..2 {
+                min[k] = min[k].min(arrow.pos[k]);
+                max[k] = max[k].max(arrow.pos[k]);
+            }
+        }
+        let half_size = ((max[0] - min[0]).max(max[1] - min[1]) * 0.5).max(1.0);
+        tree.nodes.push(Node {
+            center: [(min[0] + max[0]) * 0.5, (min[1] + max[1]) * 0.5],
+            half_size: half_size,
+            centroid: [0.0; 2],
+            count: 0,
+            dir_sum: [0.0; 2],
+            psi_offset: 0.0,
+            children: None,
+            arrows: (0..arrows.len()).collect(),
+        });
+        tree.split(0, arrows, TREE_LEAF_SIZE, 0);
+        tree
+    }
+
+    fn split(&mut self, id: usize, arrows: &[Arrow], leaf_size: usize, depth: usize) {
+        let ids = self.nodes[id].arrows.clone();
+        let mut centroid = [0.0; 2];
+        for &i in ids.iter() {
+            centroid[0] += arrows[i].pos[0];
+            centroid[1] += arrows[i].pos[1];
+        }
+        let n = ids.len() as f64;
+        self.nodes[id].centroid = [centroid[0] / n, centroid[1] / n];
+        self.nodes[id].count = ids.len();
+        if ids.len() <= leaf_size || depth >= MAX_DEPTH { return; }
+
+        let center = self.nodes[id].center;
+        let half_size = self.nodes[id].half_size * 0.5;
+        let first = self.nodes.len();
+        for k in 0..4 {
+            let dx = if k & 1 == 0 { -half_size } else { half_size };
+            let dy = if k & 2 == 0 { -half_size } else { half_size };
+            self.nodes.push(Node {
+                center: [center[0] + dx, center[1] + dy],
+                half_size: half_size,
+                centroid: [0.0; 2],
+                count: 0,
+                dir_sum: [0.0; 2],
+                psi_offset: 0.0,
+                children: None,
+                arrows: Vec::new(),
+            });
+        }
+        for &i in ids.iter() {
+            let pos = arrows[i].pos;
+            let k = if pos[0] < center[0] { 0 } else { 1 }
+                + if pos[1] < center[1] { 0 } else { 2 };
+            self.nodes[first + k].arrows.push(i);
+        }
+        self.nodes[id].arrows.clear();
+        self.nodes[id].children = Some(first);
+        for k in 0..4 {
+            if self.nodes[first + k].arrows.len() > 0 {
+                self.split(first + k, arrows, leaf_size, depth + 1);
+            }
+        }
+    }
+
+    /// Sums up the directions again, after directions or factors changed.
+    pub fn refresh(&mut self, arrows: &[Arrow], factors: Vec<f64>) {
+        self.factors = factors;
+        for id in (0..self.nodes.len()).rev() {
+            let mut dir_sum = [0.0; 2];
+            let mut psi_offset = 0.0;
+            match self.nodes[id].children {
+                None => {
+                    for &i in self.nodes[id].arrows.iter() {
+                        let (arrow, f) = (arrows[i], self.factors[i]);
+                        dir_sum[0] += arrow.dir[0] * f;
+                        dir_sum[1] += arrow.dir[1] * f;
+                        psi_offset += (arrow.dir[1] * arrow.pos[0]
+                            - arrow.dir[0] * arrow.pos[1]) * f;
+                    }
+                }
+                Some(first) => {
+                    for k in first..first + 4 {
+                        dir_sum[0] += self.nodes[k].dir_sum[0];
+                        dir_sum[1] += self.nodes[k].dir_sum[1];
+                        psi_offset += self.nodes[k].psi_offset;
+                    }
+                }
+            }
+            self.nodes[id].dir_sum = dir_sum;
+            self.nodes[id].psi_offset = psi_offset;
+        }
+    }
+
+    /// Calls back with the weight of every arrow or summed up node
+    /// that reaches a position.
+    ///
+    /// A node is summed up when its diagonal is less than `theta`
+    /// times the distance to its centroid.
+    /// Every arrow in it is then within `1 ± theta` of that distance,
+    /// so with inverse distance to the power `p` its weight is off
+    /// by at most a factor `(1 ± theta)^p`.
+    /// A Gaussian changes faster far away,
+    /// so there the node must also be smaller than `theta` times the radius.
+    /// With `theta` set to zero every arrow is visited.
+    fn gather<F>(&self, arrows: &[Arrow], kernel: &Kernel, pos: [f64; 2], theta: f64, mut f: F)
+        where F: FnMut(Part, f64)
+    {
+        if self.nodes.len() == 0 { return; }
+
+        let mut stack = vec![0];
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            if node.count == 0 || !kernel.reaches(node.distance(pos)) { continue; }
+            match node.children {
+                None => {
+                    for &i in node.arrows.iter() {
+                        f(Part::Arrow(i), kernel.weight(distance(arrows[i].pos, pos)));
+                    }
+                }
+                Some(first) => {
+                    let diagonal = node.half_size * 2.0 * ::std::f64::consts::SQRT_2;
+                    let dist = distance(node.centroid, pos);
+                    let small = match kernel.falloff {
+                            Falloff::Gaussian(radius) => diagonal < theta * radius,
+                            _ => true
+                        };
+                    // Summing up a node that is partly beyond the cutoff
+                    // would count arrows that do not reach.
+                    let inside = kernel.reaches(node.far_distance(pos));
+                    if small && inside && diagonal < theta * dist {
+                        f(Part::Node(id), kernel.weight(dist));
+                    } else {
+                        for k in 0..4 { stack.push(first + k); }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Computes the weighted average of the arrows.
+    ///
+    /// With inverse distance to the power `p`, the summed up weights are off
+    /// by at most a factor `r = ((1 + theta) / (1 - theta))^p` relative to each other,
+    /// so the result is within `2 * (r - 1)` times the longest scaled arrow
+    /// of the full weighted average. In practice it is much closer,
+    /// since the errors of arrows on either side of a centroid cancel out.
+    ///
+    /// Returns zero where no arrow is within the kernel cutoff.
+    pub fn at(
+        &self,
+        arrows: &[Arrow],
+        kernel: &Kernel,
+        pos: [f64; 2],
+        theta: f64
+    ) -> [f64; 2] {
+        use vecmath::vec2_scale as scale;
+
+        if let Falloff::Nearest = kernel.falloff {
+            return match self.nearest(arrows, kernel, pos) {
+                None => [0.0; 2],
+                Some(i) => scale(arrows[i].dir, self.factors[i])
+            };
+        }
+
+        let mut sum = [0.0; 2];
+        let mut sum_w = 0.0;
+        self.gather(arrows, kernel, pos, theta, |part, w| match part {
+            Part::Arrow(i) => {
+                sum[0] += arrows[i].dir[0] * w * self.factors[i];
+                sum[1] += arrows[i].dir[1] * w * self.factors[i];
+                sum_w += w;
+            }
+            Part::Node(id) => {
+                let node = &self.nodes[id];
+                sum[0] += node.dir_sum[0] * w;
+                sum[1] += node.dir_sum[1] * w;
+                sum_w += node.count as f64 * w;
+            }
+        });
+
+        if sum_w == 0.0 { return [0.0; 2]; }
+        scale(sum, 1.0 / sum_w)
+    }
+
+    /// Computes the flow of the weighted average of the arrow stream functions.
+    ///
+    /// Each arrow has a stream function that flows straight along it,
+    /// `dir.x * (y - pos.y) - dir.y * (x - pos.x)` scaled by its factor.
+    /// Being linear in the position, a node sums them up exactly.
+    /// The flow is `(dψ/dy, -dψ/dx)` by central differences with step `h`,
+    /// which has no divergence.
+    /// The arrows and nodes are picked once at the position,
+    /// so the differences do not jump where a node starts being summed up.
+    /// Between positions that pick different nodes, a divergence of
+    /// a few percent of the velocity gradients remains, and none with `theta` zero.
+    pub fn curl(
+        &self,
+        arrows: &[Arrow],
+        kernel: &Kernel,
+        pos: [f64; 2],
+        theta: f64,
+        h: f64
+    ) -> [f64; 2] {
+        let samples = [
+            [pos[0], pos[1] + h],
+            [pos[0], pos[1] - h],
+            [pos[0] + h, pos[1]],
+            [pos[0] - h, pos[1]]
+        ];
+        let psi = |i: usize, q: [f64; 2]| {
+            let arrow = arrows[i];
+            (arrow.dir[0] * (q[1] - arrow.pos[1])
+                - arrow.dir[1] * (q[0] - arrow.pos[0])) * self.factors[i]
+        };
+        let mut sum = [0.0; 4];
+        let mut sum_w = [0.0; 4];
+
+        if let Falloff::Nearest = kernel.falloff {
+            if let Some(i) = self.nearest(arrows, kernel, pos) {
+                for k in 0..4 {
+                    sum[k] = psi(i, samples[k]);
+                    sum_w[k] = 1.0;
+                }
+            }
+        } else {
+            self.gather(arrows, kernel, pos, theta, |part, _| for k in 0..4 {
+                let q = samples[k];
+                match part {
+                    Part::Arrow(i) => {
+                        let w = kernel.weight(distance(arrows[i].pos, q));
+                        sum[k] += psi(i, q) * w;
+                        sum_w[k] += w;
+                    }
+                    Part::Node(id) => {
+                        let node = &self.nodes[id];
+                        let w = kernel.weight(distance(node.centroid, q));
+                        sum[k] += (node.dir_sum[0] * q[1] - node.dir_sum[1] * q[0]
+                            + node.psi_offset) * w;
+                        sum_w[k] += node.count as f64 * w;
+                    }
+                }
+            });
+        }
+
+        let mut psi = [0.0; 4];
+        for k in 0..4 {
+            if sum_w[k] > 0.0 { psi[k] = sum[k] / sum_w[k]; }
+        }
+        [(psi[0] - psi[1]) / (2.0 * h), -(psi[2] - psi[3]) / (2.0 * h)]
+    }
+
+    /// Finds the closest arrow within the kernel cutoff.
+    pub fn nearest(&self, arrows: &[Arrow], kernel: &Kernel, pos: [f64; 2])
+        -> Option<usize>
+    {
+        let mut best: Option<(usize, f64)> = None;
+        let mut stack = vec![0];
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            let min_dist = node.distance(pos);
+            if node.count == 0 || !kernel.reaches(min_dist) { continue; }
+            if let Some((_, best_dist)) = best {
+                if min_dist >= best_dist { continue; }
+            }
+            match node.children {
+                None => {
+                    for &i in node.arrows.iter() {
+                        let dist = distance(arrows[i].pos, pos);
+                        if !kernel.reaches(dist) { continue; }
+                        match best {
+                            Some((_, best_dist)) if best_dist <= dist => {}
+                            _ => best = Some((i, dist))
+                        }
+                    }
+                }
+                Some(first) => {
+                    for k in 0..4 { stack.push(first + k); }
+                }
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+}
+
+impl Node {
+    /// The distance from a position to the closest point of the node square.
+    pub fn distance(&self, pos: [f64; 2]) -> f64 {
+        let dx = ((pos[0] - self.center[0]).abs() - self.half_size).max(0.0);
+        let dy = ((pos[1] - self.center[1]).abs() - self.half_size).max(0.0);
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /// The distance from a position to the furthest point of the node square.
+    pub fn far_distance(&self, pos: [f64; 2]) -> f64 {
+        let dx = (pos[0] - self.center[0]).abs() + self.half_size;
+        let dy = (pos[1] - self.center[1]).abs() + self.half_size;
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    use vecmath::vec2_sub as sub;
+    use vecmath::vec2_len as len;
+
+    len(sub(a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use kernel::{ Falloff, Kernel };
+    use stream::Arrow;
+    use world;
+    use super::{ distance, ArrowTree };
+
+    const RECT: [f64; 2] = [640.0, 480.0];
+
+    fn random_arrows(seed: u32, n: usize) -> (Vec<Arrow>, Vec<f64>) {
+        let mut rng = world::rng(seed);
+        let arrows = (0..n).map(|_| Arrow {
+                pos: [rng.next_f64() * RECT[0], rng.next_f64() * RECT[1]],
+                dir: [rng.next_f64() * 100.0 - 50.0, rng.next_f64() * 100.0 - 50.0],
+            }).collect();
+        let factors = (0..n).map(|_| rng.next_f64() * 2.0 - 1.0).collect();
+        (arrows, factors)
+    }
+
+    /// Visits every arrow.
+    fn full_average(arrows: &[Arrow], factors: &[f64], kernel: &Kernel, pos: [f64; 2])
+        -> [f64; 2]
+    {
+        let mut sum = [0.0; 2];
+        let mut sum_w = 0.0;
+        for (arrow, &f) in arrows.iter().zip(factors.iter()) {
+            let w = kernel.weight(distance(arrow.pos, pos));
+            sum[0] += arrow.dir[0] * f * w;
+            sum[1] += arrow.dir[1] * f * w;
+            sum_w += w;
+        }
+        if sum_w == 0.0 { return [0.0; 2]; }
+        [sum[0] / sum_w, sum[1] / sum_w]
+    }
+
+    /// The largest difference from the full average at random positions.
+    fn max_error(kernel: &Kernel, theta: f64) -> (f64, f64) {
+        let (arrows, factors) = random_arrows(7, 500);
+        let mut tree = ArrowTree::new(&arrows);
+        tree.refresh(&arrows, factors.clone());
+        let longest = arrows.iter().zip(factors.iter())
+            .map(|(arrow, &f)| distance(arrow.dir, [0.0; 2]) * f.abs())
+            .fold(0.0, f64::max);
+
+        let mut rng = world::rng(11);
+        let mut max_err: f64 = 0.0;
+        for _ in 0..200 {
+            let pos = [rng.next_f64() * RECT[0], rng.next_f64() * RECT[1]];
+            let expected = full_average(&arrows, &factors, kernel, pos);
+            let err = distance(tree.at(&arrows, kernel, pos, theta), expected);
+            max_err = max_err.max(err);
+        }
+        (max_err, longest)
+    }
+
+    #[test]
+    fn inverse_distance_within_bound() {
+        use settings::stream::TREE_THETA;
+
+        let power = 2.0;
+        let kernel = Kernel { falloff: Falloff::InverseDistance(power), cutoff: None };
+        let (err, longest) = max_error(&kernel, TREE_THETA);
+        let r = ((1.0 + TREE_THETA) / (1.0 - TREE_THETA)).powf(power);
+        assert!(err <= 2.0 * (r - 1.0) * longest,
+            "error {} above the bound for arrows up to {}", err, longest);
+        // The bound is loose, the average stays within a percent.
+        assert!(err <= 0.01 * longest, "error {} for arrows up to {}", err, longest);
+    }
+
+    /// The largest divergence of the curl on a grid by central differences,
+    /// and the largest sum of the gradients it is made of.
+    fn max_divergence(kernel: &Kernel, theta: f64) -> (f64, f64) {
+        use settings::stream::CURL_STEP;
+
+        let (arrows, factors) = random_arrows(3, 200);
+        let mut tree = ArrowTree::new(&arrows);
+        tree.refresh(&arrows, factors);
+        let h = CURL_STEP;
+        let curl = |x: f64, y: f64| tree.curl(&arrows, kernel, [x, y], theta, h);
+
+        let mut max_div: f64 = 0.0;
+        let mut max_gradient: f64 = 0.0;
+        for i in 0..32 {
+            for j in 0..24 {
+                let x = (i as f64 + 0.5) * RECT[0] / 32.0;
+                let y = (j as f64 + 0.5) * RECT[1] / 24.0;
+                let dx = (curl(x + h, y)[0] - curl(x - h, y)[0]) / (2.0 * h);
+                let dy = (curl(x, y + h)[1] - curl(x, y - h)[1]) / (2.0 * h);
+                max_div = max_div.max((dx + dy).abs());
+                max_gradient = max_gradient.max(dx.abs() + dy.abs());
+            }
+        }
+        (max_div, max_gradient)
+    }
+
+    #[test]
+    fn curl_without_divergence() {
+        use settings::stream::TREE_THETA;
+
+        let kernels = [
+            Kernel::new(),
+            Kernel { falloff: Falloff::Gaussian(80.0), cutoff: Some(200.0) },
+        ];
+        for kernel in kernels.iter() {
+            let (div, gradient) = max_divergence(kernel, 0.0);
+            assert!(div <= 1e-9 * gradient, "divergence {} for gradients up to {}",
+                div, gradient);
+            // Only where the sampled nodes change between neighbors.
+            let (div, gradient) = max_divergence(kernel, TREE_THETA);
+            assert!(div <= 0.05 * gradient, "divergence {} for gradients up to {}",
+                div, gradient);
+        }
+    }
+
+    #[test]
+    fn exact_without_theta() {
+        let kernels = [
+            Kernel::new(),
+            Kernel { falloff: Falloff::Gaussian(80.0), cutoff: Some(200.0) },
+        ];
+        for kernel in kernels.iter() {
+            let (err, longest) = max_error(kernel, 0.0);
+            assert!(err <= 1e-9 * longest, "error {}", err);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Maps buttons to game actions
+//!
+//! The bindings file has one action per line,
+//! followed by the buttons bound to it:
+//!
+//! ```text
+//! move_left = Left, A
+//! add_arrow = Mouse Left
+//! ```
+
+use std::fs::File;
+use std::io::{ self, Read, Write };
+use std::path::Path;
+use piston::input::{ Button, MouseButton };
+use piston::input::keyboard::Key;
+
+use editor::Tool;
+use player::{ self, KeyState };
+use primitives::PrimitiveKind;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    /// Plays again after winning or losing.
+    Restart,
+    QuickSave,
+    QuickLoad,
+    /// Switches between playing and editing the level.
+    ToggleEditor,
+    ArrowTool,
+    RockTool,
+    PalmTreeTool,
+    SeaBirdTool,
+    /// Grabs the object of the current tool under the cursor, or places a new one.
+    Grab,
+    /// Deletes the object of the current tool under the cursor.
+    Delete,
+    Undo,
+    Redo,
+    /// Writes the edited level to its file.
+    SaveLevel,
+    /// Throws away the edits since the level file was saved.
+    ReloadLevel,
+    /// Adds a sea current arrow in edit mode.
+    AddArrow,
+    /// Switches the arrow closest to the cursor to the next waveform.
+    CycleWaveform,
+    FasterOscillation,
+    SlowerOscillation,
+    /// Moves the arrow closest to the cursor ahead in its oscillation.
+    PhaseForward,
+    PhaseBack,
+    AddWhirlpool,
+    AddOutflow,
+    AddJet,
+    PrintCursorPos,
+    PrintPlayerPos,
+    PrintHasWon,
+    PrintStream,
+}
+
+/// The names used in the bindings file.
+pub static ACTIONS: &'static [(&'static str, Action)] = &[
+    ("move_left", Action::MoveLeft),
+    ("move_right", Action::MoveRight),
+    ("move_up", Action::MoveUp),
+    ("move_down", Action::MoveDown),
+    ("restart", Action::Restart),
+    ("quick_save", Action::QuickSave),
+    ("quick_load", Action::QuickLoad),
+    ("toggle_editor", Action::ToggleEditor),
+    ("arrow_tool", Action::ArrowTool),
+    ("rock_tool", Action::RockTool),
+    ("palm_tree_tool", Action::PalmTreeTool),
+    ("sea_bird_tool", Action::SeaBirdTool),
+    ("grab", Action::Grab),
+    ("delete", Action::Delete),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("save_level", Action::SaveLevel),
+    ("reload_level", Action::ReloadLevel),
+    ("add_arrow", Action::AddArrow),
+    ("cycle_waveform", Action::CycleWaveform),
+    ("faster_oscillation", Action::FasterOscillation),
+    ("slower_oscillation", Action::SlowerOscillation),
+    ("phase_forward", Action::PhaseForward),
+    ("phase_back", Action::PhaseBack),
+    ("add_whirlpool", Action::AddWhirlpool),
+    ("add_outflow", Action::AddOutflow),
+    ("add_jet", Action::AddJet),
+    ("print_cursor_pos", Action::PrintCursorPos),
+    ("print_player_pos", Action::PrintPlayerPos),
+    ("print_has_won", Action::PrintHasWon),
+    ("print_stream", Action::PrintStream),
+];
+
+pub static MOUSE_BUTTONS: &'static [(&'static str, MouseButton)] = &[
+    ("Left", MouseButton::Left),
+    ("Right", MouseButton::Right),
+    ("Middle", MouseButton::Middle),
+];
+
+pub static KEYS: &'static [(&'static str, Key)] = &[
+    ("A", Key::A), ("B", Key::B), ("C", Key::C), ("D", Key::D),
+    ("E", Key::E), ("F", Key::F), ("G", Key::G), ("H", Key::H),
+    ("I", Key::I), ("J", Key::J), ("K", Key::K), ("L", Key::L),
+    ("M", Key::M), ("N", Key::N), ("O", Key::O), ("P", Key::P),
+    ("Q", Key::Q), ("R", Key::R), ("S", Key::S), ("T", Key::T),
+    ("U", Key::U), ("V", Key::V), ("W", Key::W), ("X", Key::X),
+    ("Y", Key::Y), ("Z", Key::Z),
+    ("D0", Key::D0), ("D1", Key::D1), ("D2", Key::D2), ("D3", Key::D3),
+    ("D4", Key::D4), ("D5", Key::D5), ("D6", Key::D6), ("D7", Key::D7),
+    ("D8", Key::D8), ("D9", Key::D9),
+    ("F1", Key::F1), ("F2", Key::F2), ("F3", Key::F3), ("F4", Key::F4),
+    ("F5", Key::F5), ("F6", Key::F6), ("F7", Key::F7), ("F8", Key::F8),
+    ("F9", Key::F9), ("F10", Key::F10), ("F11", Key::F11), ("F12", Key::F12),
+    ("Left", Key::Left), ("Right", Key::Right), ("Up", Key::Up), ("Down", Key::Down),
+    ("Return", Key::Return), ("Space", Key::Space), ("Tab", Key::Tab),
+    ("Backspace", Key::Backspace), ("Delete", Key::Delete), ("Insert", Key::Insert),
+    ("Home", Key::Home), ("End", Key::End),
+    ("PageUp", Key::PageUp), ("PageDown", Key::PageDown),
+    ("LShift", Key::LShift), ("RShift", Key::RShift),
+    ("LCtrl", Key::LCtrl), ("RCtrl", Key::RCtrl),
+    ("LAlt", Key::LAlt), ("RAlt", Key::RAlt),
+    ("Minus", Key::Minus), ("Equals", Key::Equals),
+    ("Comma", Key::Comma), ("Period", Key::Period), ("Slash", Key::Slash),
+    ("Semicolon", Key::Semicolon), ("Quote", Key::Quote),
+    ("LeftBracket", Key::LeftBracket), ("RightBracket", Key::RightBracket),
+    ("Backslash", Key::Backslash),
+    ("NumPad0", Key::NumPad0), ("NumPad1", Key::NumPad1), ("NumPad2", Key::NumPad2),
+    ("NumPad3", Key::NumPad3), ("NumPad4", Key::NumPad4), ("NumPad5", Key::NumPad5),
+    ("NumPad6", Key::NumPad6), ("NumPad7", Key::NumPad7), ("NumPad8", Key::NumPad8),
+    ("NumPad9", Key::NumPad9),
+];
+
+/// Reads a button, such as `Left`, `F5` or `Mouse Left`.
+pub fn parse_button(name: &str) -> Option<Button> {
+    let name = name.trim();
+    if name.starts_with("Mouse ") {
+        let name = name["Mouse ".len()..].trim();
+        MOUSE_BUTTONS.iter().find(|&&(x, _)| x == name)
+            .map(|&(_, button)| Button::Mouse(button))
+    } else {
+        KEYS.iter().find(|&&(x, _)| x == name)
+            .map(|&(_, key)| Button::Keyboard(key))
+    }
+}
+
+pub fn button_name(button: Button) -> Option<String> {
+    match button {
+        Button::Keyboard(key) => KEYS.iter().find(|&&(_, x)| x == key)
+            .map(|&(name, _)| name.to_string()),
+        Button::Mouse(button) => MOUSE_BUTTONS.iter().find(|&&(_, x)| x == button)
+            .map(|&(name, _)| format!("Mouse {}", name)),
+    }
+}
+
+/// The primitive placed by an action.
+pub fn primitive_kind(action: Action) -> Option<PrimitiveKind> {
+    match action {
+        Action::AddWhirlpool => Some(PrimitiveKind::Whirlpool),
+        Action::AddOutflow => Some(PrimitiveKind::Outflow),
+        Action::AddJet => Some(PrimitiveKind::Jet),
+        _ => None
+    }
+}
+
+/// The editor tool picked by an action.
+pub fn tool(action: Action) -> Option<Tool> {
+    match action {
+        Action::ArrowTool => Some(Tool::Arrows),
+        Action::RockTool => Some(Tool::Rocks),
+        Action::PalmTreeTool => Some(Tool::PalmTrees),
+        Action::SeaBirdTool => Some(Tool::SeaBirds),
+        _ => None
+    }
+}
+
+/// The movement key controlled by an action.
+pub fn movement_key(action: Action) -> Option<KeyState> {
+    match action {
+        Action::MoveLeft => Some(player::LEFT),
+        Action::MoveRight => Some(player::RIGHT),
+        Action::MoveUp => Some(player::UP),
+        Action::MoveDown => Some(player::DOWN),
+        _ => None
+    }
+}
+
+/// Buttons bound to actions.
+///
+/// An action can have several buttons,
+/// and a button can trigger several actions.
+#[derive(Clone)]
+pub struct Bindings {
+    pub bindings: Vec<(Action, Button)>,
+}
+
+impl Bindings {
+    /// The buttons from `settings`.
+    pub fn new() -> Bindings {
+        use settings;
+
+        Bindings {
+            bindings: vec![
+                (Action::MoveLeft, settings::player::MOVE_LEFT_BUTTON),
+                (Action::MoveRight, settings::player::MOVE_RIGHT_BUTTON),
+                (Action::MoveUp, settings::player::MOVE_UP_BUTTON),
+                (Action::MoveDown, settings::player::MOVE_DOWN_BUTTON),
+                (Action::Restart, settings::utils::RESTART_LEVEL),
+                (Action::QuickSave, settings::utils::QUICK_SAVE),
+                (Action::QuickLoad, settings::utils::QUICK_LOAD),
+                (Action::ToggleEditor, settings::editor::TOGGLE),
+                (Action::ArrowTool, settings::editor::ARROW_TOOL),
+                (Action::RockTool, settings::editor::ROCK_TOOL),
+                (Action::PalmTreeTool, settings::editor::PALM_TREE_TOOL),
+                (Action::SeaBirdTool, settings::editor::SEA_BIRD_TOOL),
+                (Action::Grab, settings::editor::GRAB),
+                (Action::Delete, settings::editor::DELETE),
+                (Action::Undo, settings::editor::UNDO),
+                (Action::Redo, settings::editor::REDO),
+                (Action::SaveLevel, settings::editor::SAVE_LEVEL),
+                (Action::ReloadLevel, settings::editor::RELOAD_LEVEL),
+                (Action::AddArrow, settings::stream::ADD_ARROW_BUTTON),
+                (Action::CycleWaveform, settings::stream::CYCLE_WAVEFORM),
+                (Action::FasterOscillation, settings::stream::FASTER_OSCILLATION),
+                (Action::SlowerOscillation, settings::stream::SLOWER_OSCILLATION),
+                (Action::PhaseForward, settings::stream::PHASE_FORWARD),
+                (Action::PhaseBack, settings::stream::PHASE_BACK),
+                (Action::AddWhirlpool, settings::primitives::ADD_WHIRLPOOL),
+                (Action::AddOutflow, settings::primitives::ADD_OUTFLOW),
+                (Action::AddJet, settings::primitives::ADD_JET),
+                (Action::PrintCursorPos, settings::utils::PRINT_CURSOR_POS),
+                (Action::PrintPlayerPos, settings::utils::PRINT_PLAYER_POS),
+                (Action::PrintHasWon, settings::utils::PRINT_HAS_WON),
+                (Action::PrintStream, settings::utils::PRINT_STREAM),
+            ],
+        }
+    }
+
+    /// Returns the actions triggered by a button.
+    pub fn actions(&self, button: Button) -> Vec<Action> {
+        self.bindings.iter()
+            .filter(|&&(_, x)| x == button)
+            .map(|&(action, _)| action)
+            .collect()
+    }
+
+    /// Returns the buttons bound to an action.
+    pub fn buttons(&self, action: Action) -> Vec<Button> {
+        self.bindings.iter()
+            .filter(|&&(x, _)| x == action)
+            .map(|&(_, button)| button)
+            .collect()
+    }
+
+    /// Reads bindings, replacing the defaults of the actions listed.
+    ///
+    /// An action with nothing after `=` is unbound.
+    pub fn parse(data: &str) -> Result<Bindings, String> {
+        let mut bindings = Bindings::new();
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.len() == 0 || line.starts_with("#") { continue; }
+
+            let (name, buttons) = match line.find('=') {
+                None => {
+                    return Err(format!("line {}: expected `action = button, ...`",
+                        i + 1));
+                }
+                Some(x) => (line[..x].trim(), line[x + 1..].trim())
+            };
+            let action = match ACTIONS.iter().find(|&&(x, _)| x == name) {
+                None => {
+                    return Err(format!("line {}: unknown action `{}`", i + 1, name));
+                }
+                Some(&(_, action)) => action
+            };
+            bindings.bindings.retain(|&(x, _)| x != action);
+            for button in buttons.split(",").filter(|x| x.trim().len() > 0) {
+                match parse_button(button) {
+                    None => {
+                        return Err(format!("line {}: unknown button `{}`",
+                            i + 1, button.trim()));
+                    }
+                    Some(button) => bindings.bindings.push((action, button))
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Reads a bindings file.
+    pub fn from_path(path: &Path) -> Result<Bindings, String> {
+        let mut data = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut data)) {
+            Ok(_) => {}
+            Err(err) => {
+                return Err(format!("{}: {}", path.display(), err));
+            }
+        }
+        Bindings::parse(&data).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Writes every action with its buttons.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut file = try!(File::create(path));
+        for &(name, action) in ACTIONS.iter() {
+            let buttons: Vec<String> = self.buttons(action).into_iter()
+                .filter_map(|button| button_name(button))
+                .collect();
+            try!(writeln!(file, "{} = {}", name, buttons.connect(", ")));
+        }
+        Ok(())
+    }
+}
+
+/// The buttons that are down.
+///
+/// With several buttons bound to one movement,
+/// the movement stops when the last of them goes up.
+pub struct HeldButtons {
+    pub buttons: Vec<Button>,
+}
+
+impl HeldButtons {
+    pub fn new() -> HeldButtons {
+        HeldButtons { buttons: Vec::new() }
+    }
+
+    pub fn press(&mut self, button: Button) {
+        if !self.buttons.contains(&button) {
+            self.buttons.push(button);
+        }
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.buttons.retain(|&x| x != button);
+    }
+
+    /// Whether any button bound to an action is down.
+    pub fn is_held(&self, bindings: &Bindings, action: Action) -> bool {
+        bindings.buttons(action).iter().any(|x| self.buttons.contains(x))
+    }
+}
@@ -1,11 +1,14 @@
+use integrator;
+use world::World;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable)]
 pub struct BloodDrop {
     pub pos: [f64; 2],
     pub time: f64,
     pub dead: bool,
 }
 
+#[derive(Clone, RustcEncodable, RustcDecodable)]
 pub struct Blood {
     pub blood_drops: Vec<BloodDrop>,
     pub time_since_last_drop: f64,
@@ -26,19 +29,10 @@ impl Blood {
     }
 }
 
-pub fn update_blood(dt: f64) {
-    use current_blood;
-    use current_player;
-    use current_stream;
-    use vecmath::vec2_add as add;
-    use vecmath::vec2_scale as scale;
-    use settings::blood::{ DROP_INTERVAL, SPAN };
+pub fn update_blood(w: &mut World, dt: f64) {
+    let World { ref mut blood, ref player, ref stream, ref config, .. } = *w;
 
-    let blood = unsafe { &mut *current_blood() };
-    let player = unsafe { &mut *current_player() };
-    let stream = unsafe { &mut *current_stream() };
-
-    let interval = DROP_INTERVAL;
+    let interval = config.blood.drop_interval;
     blood.time_since_last_drop += dt;
     if blood.time_since_last_drop > interval {
         blood.time_since_last_drop -= interval;
@@ -51,9 +45,10 @@ pub fn update_blood(dt: f64) {
 
     // Make blood drops follow stream
     for blood_drop in blood.blood_drops.iter_mut() {
-        blood_drop.pos = add(blood_drop.pos, scale(stream.at(blood_drop.pos), dt));
+        blood_drop.pos = integrator::advect(config.integrator, blood_drop.pos, dt,
+            |pos| stream.at(pos));
         blood_drop.time += dt;
-        if blood_drop.time > SPAN {
+        if blood_drop.time > config.blood.span {
             blood_drop.dead = true;
         }
     }
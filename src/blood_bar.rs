@@ -1,16 +1,13 @@
+use world::World;
 
 pub struct BloodBar(pub f64);
 
-pub fn decrease(d: f64) {
-    use current_blood_bar;
-
-    let &mut BloodBar(ref mut val) = unsafe { &mut *current_blood_bar() };
+pub fn decrease(blood_bar: &mut BloodBar, d: f64) {
+    let &mut BloodBar(ref mut val) = blood_bar;
     *val = (*val - d).max(0.0);
 }
 
-pub fn update_blood_bar(dt: f64) {
-    use settings::blood_bar::DEC_VAL;
-    
-    decrease(dt * DEC_VAL);
+pub fn update_blood_bar(w: &mut World, dt: f64) {
+    let dec_val = w.config.blood_bar.dec_val;
+    decrease(&mut w.blood_bar, dt * dec_val);
 }
-
@@ -0,0 +1,100 @@
+//! An ordered list of levels and the player's progress through them
+
+use std::fs::File;
+use std::io::{ Read, Write };
+use std::path::{ Path, PathBuf };
+
+use level::{ Level, LevelError };
+
+pub struct Campaign {
+    /// Level files in the order they are played.
+    pub levels: Vec<PathBuf>,
+    /// The level being played.
+    pub current: usize,
+    /// The highest level the player has reached.
+    pub unlocked: usize,
+    /// Where progress is remembered between sessions.
+    pub progress_path: Option<PathBuf>,
+}
+
+impl Campaign {
+    /// Reads a campaign file, listing one level file per line.
+    ///
+    /// Paths are relative to the campaign file.
+    /// Empty lines and lines starting with `#` are ignored.
+    /// Play continues from the highest level unlocked in the progress file.
+    pub fn from_path(path: &Path, progress_path: &Path) -> Result<Campaign, String> {
+        let mut data = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut data)) {
+            Ok(_) => {}
+            Err(err) => {
+                return Err(format!("{}: {}", path.display(), err));
+            }
+        }
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let levels: Vec<PathBuf> = data.lines()
+            .map(|line| line.trim())
+            .filter(|line| line.len() > 0 && !line.starts_with("#"))
+            .map(|line| dir.join(line))
+            .collect();
+        if levels.len() == 0 {
+            return Err(format!("{}: no levels in campaign", path.display()));
+        }
+        let unlocked = load_progress(progress_path).min(levels.len() - 1);
+        Ok(Campaign {
+            levels: levels,
+            current: unlocked,
+            unlocked: unlocked,
+            progress_path: Some(progress_path.to_path_buf()),
+        })
+    }
+
+    /// A campaign of a single level, without remembering progress.
+    pub fn single(path: &Path) -> Campaign {
+        Campaign {
+            levels: vec![path.to_path_buf()],
+            current: 0,
+            unlocked: 0,
+            progress_path: None,
+        }
+    }
+
+    /// Reads the current level from disk.
+    pub fn level(&self) -> Result<Level, LevelError> {
+        Level::from_path(&self.levels[self.current])
+    }
+
+    /// Moves on to the next level and unlocks it.
+    ///
+    /// Winning the last level starts over from the first.
+    pub fn win(&mut self) {
+        let next = self.current + 1;
+        if next < self.levels.len() && next > self.unlocked {
+            self.unlocked = next;
+            self.save_progress();
+        }
+        self.current = next % self.levels.len();
+    }
+
+    /// Writes the highest unlocked level to the progress file.
+    pub fn save_progress(&self) {
+        let path = match self.progress_path {
+            None => { return; }
+            Some(ref x) => x
+        };
+        let data = format!("{}\n", self.unlocked);
+        match File::create(path).and_then(|mut f| f.write_all(data.as_bytes())) {
+            Ok(()) => {}
+            Err(err) => println!("Could not save progress: {}: {}", path.display(), err)
+        }
+    }
+}
+
+/// Reads the highest unlocked level, or starts from the beginning.
+fn load_progress(path: &Path) -> usize {
+    let mut data = String::new();
+    match File::open(path).and_then(|mut f| f.read_to_string(&mut data)) {
+        Ok(_) => data.trim().parse().unwrap_or(0),
+        Err(_) => 0
+    }
+}
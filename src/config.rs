@@ -0,0 +1,347 @@
+//! Tuning values loaded at runtime, overriding the `settings` constants
+//!
+//! The config file has one `key = value` per line,
+//! where the key is the lower case path of the constant in `settings`:
+//!
+//! ```text
+//! water_friction = 0.0004
+//! player.acc = 50.0
+//! sea_birds.circling.radius = 50.0
+//! ```
+//!
+//! Keys that are left out keep their default value.
+//! The `integrator` key takes a name, `euler`, `midpoint` or `rk4`.
+//!
+//! A level can override the numbers with its own `config`, using the same keys.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{ Path, PathBuf };
+
+use integrator::{ self, Scheme };
+
+#[derive(Clone)]
+pub struct PlayerConfig {
+    pub speedup: f64,
+    pub acc: f64,
+    pub bitten_fade_out_seconds: f64,
+}
+
+#[derive(Clone)]
+pub struct StreamConfig {
+    pub speedup: f64,
+    pub phase_vel: f64,
+    pub moving_arrow_time_span: f64,
+    pub field_resolution: f64,
+}
+
+#[derive(Clone)]
+pub struct BloodBarConfig {
+    pub dec_val: f64,
+}
+
+#[derive(Clone)]
+pub struct BloodConfig {
+    pub drop_interval: f64,
+    pub span: f64,
+}
+
+#[derive(Clone)]
+pub struct RocksConfig {
+    pub radius: f64,
+}
+
+#[derive(Clone)]
+pub struct CirclingConfig {
+    pub n: f64,
+    pub advance_radius: f64,
+    pub speed: f64,
+    pub radius: f64,
+}
+
+#[derive(Clone)]
+pub struct SeaBirdsConfig {
+    pub speedup: f64,
+    pub circling: CirclingConfig,
+}
+
+/// The values a key accepts.
+#[derive(Copy, Clone)]
+enum Bound {
+    /// Above zero, such as speeds that are divided by.
+    Positive,
+    NonNegative,
+    /// Zero to turn something off, or at least a minimum.
+    ZeroOrAtLeast(f64),
+}
+
+/// The keys with a number, in the order they are written.
+static KEYS: &'static [&'static str] = &[
+    "water_friction",
+    "player.speedup",
+    "player.acc",
+    "player.bitten_fade_out_seconds",
+    "stream.speedup",
+    "stream.phase_vel",
+    "stream.moving_arrow_time_span",
+    "stream.field_resolution",
+    "blood_bar.dec_val",
+    "blood.drop_interval",
+    "blood.span",
+    "rocks.radius",
+    "sea_birds.speedup",
+    "sea_birds.circling.n",
+    "sea_birds.circling.advance_radius",
+    "sea_birds.circling.speed",
+    "sea_birds.circling.radius",
+];
+
+/// Tuning values used by the simulation.
+#[derive(Clone)]
+pub struct Config {
+    pub water_friction: f64,
+    pub integrator: Scheme,
+    pub player: PlayerConfig,
+    pub stream: StreamConfig,
+    pub blood_bar: BloodBarConfig,
+    pub blood: BloodConfig,
+    pub rocks: RocksConfig,
+    pub sea_birds: SeaBirdsConfig,
+}
+
+impl Config {
+    /// The values from `settings`.
+    pub fn new() -> Config {
+        use settings;
+
+        Config {
+            water_friction: settings::WATER_FRICTION,
+            integrator: settings::INTEGRATOR,
+            player: PlayerConfig {
+                speedup: settings::player::SPEEDUP,
+                acc: settings::player::ACC,
+                bitten_fade_out_seconds: settings::player::BITTEN_FADE_OUT_SECONDS,
+            },
+            stream: StreamConfig {
+                speedup: settings::stream::SPEEDUP,
+                phase_vel: settings::stream::PHASE_VEL,
+                moving_arrow_time_span: settings::stream::MOVING_ARROW_TIME_SPAN,
+                field_resolution: settings::stream::FIELD_RESOLUTION,
+            },
+            blood_bar: BloodBarConfig {
+                dec_val: settings::blood_bar::DEC_VAL,
+            },
+            blood: BloodConfig {
+                drop_interval: settings::blood::DROP_INTERVAL,
+                span: settings::blood::SPAN,
+            },
+            rocks: RocksConfig {
+                radius: settings::rocks::RADIUS,
+            },
+            sea_birds: SeaBirdsConfig {
+                speedup: settings::sea_birds::SPEEDUP,
+                circling: CirclingConfig {
+                    n: settings::sea_birds::circling::N,
+                    advance_radius: settings::sea_birds::circling::ADVANCE_RADIUS,
+                    speed: settings::sea_birds::circling::SPEED,
+                    radius: settings::sea_birds::circling::RADIUS,
+                },
+            },
+        }
+    }
+
+    /// Returns the value for a key and the values it accepts,
+    /// or `None` if there is no such key.
+    fn value_mut(&mut self, key: &str) -> Option<(&mut f64, Bound)> {
+        use self::Bound::*;
+        use settings;
+
+        Some(match key {
+            "water_friction" => (&mut self.water_friction, NonNegative),
+            "player.speedup" => (&mut self.player.speedup, Positive),
+            "player.acc" => (&mut self.player.acc, NonNegative),
+            "player.bitten_fade_out_seconds" =>
+                (&mut self.player.bitten_fade_out_seconds, Positive),
+            "stream.speedup" => (&mut self.stream.speedup, Positive),
+            "stream.phase_vel" => (&mut self.stream.phase_vel, NonNegative),
+            "stream.moving_arrow_time_span" =>
+                (&mut self.stream.moving_arrow_time_span, Positive),
+            "stream.field_resolution" => (&mut self.stream.field_resolution,
+                ZeroOrAtLeast(settings::stream::MIN_FIELD_RESOLUTION)),
+            "blood_bar.dec_val" => (&mut self.blood_bar.dec_val, NonNegative),
+            "blood.drop_interval" => (&mut self.blood.drop_interval, Positive),
+            "blood.span" => (&mut self.blood.span, Positive),
+            "rocks.radius" => (&mut self.rocks.radius, Positive),
+            "sea_birds.speedup" => (&mut self.sea_birds.speedup, Positive),
+            "sea_birds.circling.n" => (&mut self.sea_birds.circling.n, Positive),
+            "sea_birds.circling.advance_radius" =>
+                (&mut self.sea_birds.circling.advance_radius, Positive),
+            "sea_birds.circling.speed" =>
+                (&mut self.sea_birds.circling.speed, Positive),
+            "sea_birds.circling.radius" =>
+                (&mut self.sea_birds.circling.radius, Positive),
+            _ => { return None; }
+        })
+    }
+
+    /// Changes the value of a key, if the key accepts it.
+    pub fn set(&mut self, key: &str, value: f64) -> Result<(), String> {
+        match self.value_mut(key) {
+            None => Err(format!("unknown key `{}`", key)),
+            Some((x, bound)) => {
+                if !value.is_finite() {
+                    return Err(format!("`{}` must be a finite number, found `{}`",
+                        key, value));
+                }
+                match bound {
+                    Bound::Positive if value <= 0.0 => {
+                        return Err(format!("`{}` must be above zero, found `{}`",
+                            key, value));
+                    }
+                    Bound::NonNegative if value < 0.0 => {
+                        return Err(format!("`{}` must not be negative, found `{}`",
+                            key, value));
+                    }
+                    Bound::ZeroOrAtLeast(min) if value != 0.0 && value < min => {
+                        return Err(format!("`{}` must be zero or at least {}, found `{}`",
+                            key, min, value));
+                    }
+                    _ => {}
+                }
+                *x = value;
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns a copy with the values of a level on top.
+    pub fn with_overrides(&self, overrides: &BTreeMap<String, f64>)
+        -> Result<Config, String>
+    {
+        let mut config = self.clone();
+        for (key, &value) in overrides.iter() {
+            try!(config.set(key, value));
+        }
+        Ok(config)
+    }
+
+    /// Reads values on top of the defaults.
+    pub fn parse(data: &str) -> Result<Config, String> {
+        let mut config = Config::new();
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.len() == 0 || line.starts_with("#") { continue; }
+
+            try!(config.parse_line(line).map_err(|err| format!("line {}: {}", i + 1, err)));
+        }
+        Ok(config)
+    }
+
+    /// Reads one `key = value` line.
+    pub fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let (key, value) = match line.find('=') {
+            None => { return Err("expected `key = value`".to_string()); }
+            Some(x) => (line[..x].trim(), line[x + 1..].trim())
+        };
+        if key == "integrator" {
+            self.integrator = match integrator::parse_scheme(value) {
+                None => { return Err(format!("unknown integrator `{}`", value)); }
+                Some(x) => x
+            };
+            return Ok(());
+        }
+        let value: f64 = match value.parse() {
+                Ok(x) => x,
+                Err(_) => { return Err(format!("invalid number `{}`", value)); }
+            };
+        self.set(key, value)
+    }
+
+    /// Reads a config file, or uses the defaults if there is none.
+    pub fn from_path(path: &Path) -> Result<Config, String> {
+        let mut data = String::new();
+        match File::open(path) {
+            Err(_) => Ok(Config::new()),
+            Ok(mut f) => match f.read_to_string(&mut data) {
+                Ok(_) => Config::parse(&data)
+                    .map_err(|err| format!("{}: {}", path.display(), err)),
+                Err(err) => Err(format!("{}: {}", path.display(), err))
+            }
+        }
+    }
+}
+
+/// Writes every value in the config file format,
+/// which `Config::parse` reads back exactly.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "integrator = {}", integrator::scheme_name(self.integrator)));
+        let mut config = self.clone();
+        for key in KEYS.iter() {
+            let (value, _) = config.value_mut(key).unwrap();
+            try!(writeln!(f, "{} = {}", key, *value));
+        }
+        Ok(())
+    }
+}
+
+/// Checks a config file for changes now and then.
+pub struct ConfigWatch {
+    pub path: PathBuf,
+    /// The values of the level, applied on top of the file.
+    pub overrides: BTreeMap<String, f64>,
+    /// The file content last time it was read.
+    pub data: Option<String>,
+    pub time_since_check: f64,
+}
+
+impl ConfigWatch {
+    pub fn new(path: &Path, overrides: BTreeMap<String, f64>) -> ConfigWatch {
+        let mut watch = ConfigWatch {
+            path: path.to_path_buf(),
+            overrides: overrides,
+            data: None,
+            time_since_check: 0.0,
+        };
+        watch.data = watch.read();
+        watch
+    }
+
+    fn read(&self) -> Option<String> {
+        let mut data = String::new();
+        match File::open(&self.path).and_then(|mut f| f.read_to_string(&mut data)) {
+            Ok(_) => Some(data),
+            Err(_) => None
+        }
+    }
+
+    /// Returns the new config when the file has changed.
+    ///
+    /// Invalid files are reported and ignored,
+    /// so a typo does not stop the running game.
+    pub fn update(&mut self, dt: f64) -> Option<Config> {
+        use settings::config::WATCH_INTERVAL;
+
+        self.time_since_check += dt;
+        if self.time_since_check < WATCH_INTERVAL { return None; }
+        self.time_since_check = 0.0;
+
+        let data = self.read();
+        if data == self.data { return None; }
+        self.data = data;
+        let config = match self.data {
+                None => Ok(Config::new()),
+                Some(ref data) => Config::parse(data)
+                    .map_err(|err| format!("{}: {}", self.path.display(), err))
+            };
+        match config.and_then(|config| config.with_overrides(&self.overrides)) {
+            Ok(config) => Some(config),
+            Err(err) => {
+                println!("{}", err);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Editing the level while the game is paused
+
+use history::{ self, History, Target };
+use level::Level;
+use primitives::{ self, PrimitiveKind };
+use stream;
+use world::World;
+
+/// The kind of object the grab and delete actions work on.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Tool {
+    /// Moves, aims and deletes sea current arrows.
+    Arrows,
+    Rocks,
+    PalmTrees,
+    /// The targets the sea birds circle around.
+    SeaBirds,
+}
+
+pub struct Editor {
+    pub tool: Tool,
+    /// The object following the cursor.
+    pub grabbed: Option<usize>,
+    pub history: History,
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor {
+            tool: Tool::Rocks,
+            grabbed: None,
+            history: History::new(),
+        }
+    }
+}
+
+/// Switches between playing and editing.
+///
+/// The simulation stands still while editing,
+/// so playing continues from the same state.
+pub fn toggle(w: &mut World) {
+    history::end(w);
+    w.editing = !w.editing;
+    w.editor.grabbed = None;
+    stream::deselect_arrow(w);
+    primitives::deselect_primitive(w);
+}
+
+pub fn select_tool(w: &mut World, tool: Tool) {
+    w.editor.tool = tool;
+    w.editor.grabbed = None;
+}
+
+/// The kind of object a tool changes.
+pub fn target(tool: Tool) -> Target {
+    match tool {
+        Tool::Arrows => Target::Arrows,
+        Tool::Rocks => Target::Rocks,
+        Tool::PalmTrees => Target::PalmTrees,
+        Tool::SeaBirds => Target::SeaBirds,
+    }
+}
+
+/// The positions of the objects of a tool.
+pub fn positions(w: &World, tool: Tool) -> Vec<[f64; 2]> {
+    match tool {
+        Tool::Arrows => w.stream.arrows.iter().map(|arrow| arrow.pos).collect(),
+        Tool::Rocks => w.rocks.rocks.iter().map(|rock| rock.pos).collect(),
+        Tool::PalmTrees => w.palm_trees.palms.clone(),
+        Tool::SeaBirds => w.sea_birds.birds.iter().map(|bird| bird.target).collect(),
+    }
+}
+
+/// Finds the object of the current tool closest to a position,
+/// within `settings::editor::PICK_RADIUS`.
+pub fn pick(w: &World, pos: [f64; 2]) -> Option<usize> {
+    use vecmath::vec2_sub as sub;
+    use vecmath::vec2_len as len;
+    use settings::editor::PICK_RADIUS;
+
+    let mut best: Option<(usize, f64)> = None;
+    for (i, &x) in positions(w, w.editor.tool).iter().enumerate() {
+        let d = len(sub(x, pos));
+        if d > PICK_RADIUS { continue; }
+        match best {
+            Some((_, best_d)) if best_d <= d => {}
+            _ => best = Some((i, d))
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+/// Grabs the object under the cursor, or places a new one there.
+///
+/// Arrows are grabbed by the base to move them, or by the tip to aim them.
+pub fn grab(w: &mut World, pos: [f64; 2]) {
+    use rocks::Rock;
+    use sea_birds::SeaBird;
+
+    if w.editor.tool == Tool::Arrows {
+        if stream::select_arrow(w, pos) {
+            if let stream::SelectedArrow(Some((id, _))) = w.selected_arrow {
+                history::begin(w, Target::Arrows, id, false);
+            }
+        } else {
+            add_arrow(w, pos);
+        }
+        return;
+    }
+    let target = target(w.editor.tool);
+    let id = match pick(w, pos) {
+        Some(id) => {
+            history::begin(w, target, id, false);
+            id
+        }
+        None => {
+            match w.editor.tool {
+                Tool::Rocks => {
+                    w.rocks.rocks.push(Rock { pos: pos });
+                    stream::refresh_obstacles(w);
+                }
+                Tool::PalmTrees => w.palm_trees.palms.push(pos),
+                Tool::SeaBirds => {
+                    let behavior = w.sea_birds.behavior.clone();
+                    w.sea_birds.birds.push(SeaBird::new(pos, pos, behavior));
+                }
+                Tool::Arrows => unreachable!()
+            }
+            let id = positions(w, w.editor.tool).len() - 1;
+            history::begin(w, target, id, true);
+            id
+        }
+    };
+    w.editor.grabbed = Some(id);
+}
+
+/// Places an arrow and aims it with the cursor until released.
+pub fn add_arrow(w: &mut World, pos: [f64; 2]) {
+    stream::add_arrow(w, pos);
+    let id = w.stream.arrows.len() - 1;
+    history::begin(w, Target::Arrows, id, true);
+    stream::refresh_moving_arrows(w);
+}
+
+/// Places a primitive and shapes it with the cursor until released.
+pub fn add_primitive(w: &mut World, kind: PrimitiveKind, pos: [f64; 2]) {
+    history::begin(w, Target::Primitives, 0, false);
+    primitives::add_primitive(w, kind, pos);
+}
+
+/// Changes the arrow closest to the cursor, so it can be undone.
+pub fn change_arrow<F>(w: &mut World, pos: [f64; 2], f: F)
+    where F: FnOnce(&mut World, [f64; 2])
+{
+    let id = match w.stream.closest_arrow(pos) {
+        None => { return; }
+        Some(x) => x
+    };
+    history::begin(w, Target::Arrows, id, false);
+    f(w, pos);
+    history::end(w);
+}
+
+/// Moves the grabbed object to the cursor.
+pub fn drag(w: &mut World, pos: [f64; 2]) {
+    let id = match w.editor.grabbed {
+        None => { return; }
+        Some(x) => x
+    };
+    match w.editor.tool {
+        Tool::Rocks => {
+            w.rocks.rocks[id].pos = pos;
+            stream::refresh_obstacles(w);
+        }
+        Tool::PalmTrees => w.palm_trees.palms[id] = pos,
+        Tool::SeaBirds => w.sea_birds.birds[id].target = pos,
+        // Follows the cursor in `stream::edit_selected_arrow`.
+        Tool::Arrows => {}
+    }
+}
+
+/// Lets go of the grabbed object, arrow or primitive.
+pub fn release(w: &mut World) {
+    history::end(w);
+    w.editor.grabbed = None;
+    stream::deselect_arrow(w);
+    primitives::deselect_primitive(w);
+    stream::refresh_moving_arrows(w);
+}
+
+/// Removes the object of the current tool under the cursor.
+pub fn delete(w: &mut World, pos: [f64; 2]) {
+    use history::Command;
+    use settings::editor::PICK_RADIUS;
+
+    let id = if w.editor.tool == Tool::Arrows {
+            let speedup = w.config.stream.speedup;
+            w.stream.hit_arrow(pos, PICK_RADIUS, speedup).map(|(id, _)| id)
+        } else {
+            pick(w, pos)
+        };
+    let id = match id {
+        None => { return; }
+        Some(x) => x
+    };
+    release(w);
+    let object = history::get(w, target(w.editor.tool), id);
+    history::run(w, Command::Remove(id, object));
+}
+
+/// The level as it is edited.
+///
+/// Arrows start at their current phase, and generated arrows are kept as they are.
+/// The seed, start position, blood bar and tuning values are taken from `base`,
+/// since the editor does not change them.
+pub fn level(w: &World, base: &Level) -> Level {
+    use level::LevelArrow;
+
+    let stream = &w.stream;
+    let arrows = stream.arrows.iter()
+        .zip(stream.arrow_phases.iter())
+        .zip(stream.arrow_oscillations.iter())
+        .map(|((arrow, &phase), &oscillation)| LevelArrow {
+            pos: arrow.pos,
+            dir: arrow.dir,
+            phase: phase,
+            oscillation: Some(oscillation),
+        })
+        .collect();
+    Level {
+        seed: base.seed,
+        beach: w.beach,
+        start_pos: base.start_pos,
+        blood_bar_start: base.blood_bar_start,
+        strength: stream.strength,
+        arrows: arrows,
+        kernel: Some(stream.kernel),
+        model: Some(stream.model),
+        primitives: Some(stream.primitives.clone()),
+        generate: None,
+        rocks: w.rocks.rocks.iter().map(|rock| rock.pos).collect(),
+        palm_trees: w.palm_trees.palms.clone(),
+        sea_birds: w.sea_birds.birds.iter().map(|bird| bird.target).collect(),
+        config: base.config.clone(),
+    }
+}
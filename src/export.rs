@@ -0,0 +1,113 @@
+//! Writes the sea current to files for reviewing without the game
+//!
+//! The CSV file has one line per sample with position and direction.
+//! The PNG image shows direction as hue and speed as brightness,
+//! with the stream arrows drawn on top.
+
+use std::fs::File;
+use std::io::{ self, Write };
+use std::path::Path;
+use image::{ ImageBuffer, Rgb };
+
+use flow_field::FlowField;
+use stream::Stream;
+
+/// Samples the stream every `resolution` pixels over its rectangle,
+/// writing the samples next to `path` with the extensions "csv" and "png".
+/// The resolution must be above zero.
+///
+/// Arrows are drawn `speedup` times their direction, like in the game.
+pub fn export(stream: &Stream, resolution: f64, speedup: f64, path: &Path)
+    -> io::Result<()>
+{
+    let field = FlowField::bake(stream.rect, resolution, |pos| stream.at_unbaked(pos));
+    try!(write_csv(&field, &path.with_extension("csv")));
+    write_png(stream, &field, speedup, &path.with_extension("png"))
+}
+
+fn write_csv(field: &FlowField, path: &Path) -> io::Result<()> {
+    let mut file = try!(File::create(path));
+    try!(writeln!(file, "x,y,dx,dy"));
+    let (n, m) = (field.nm[0], field.nm[1]);
+    for j in 0..m {
+        for i in 0..n {
+            let dir = field.samples[j * n + i];
+            try!(writeln!(file, "{},{},{},{}",
+                field.rect[0] + i as f64 * field.cell_size,
+                field.rect[1] + j as f64 * field.cell_size,
+                dir[0], dir[1]));
+        }
+    }
+    Ok(())
+}
+
+fn write_png(stream: &Stream, field: &FlowField, speedup: f64, path: &Path)
+    -> io::Result<()>
+{
+    use vecmath::vec2_len as len;
+    use settings::export::ARROW_COLOR;
+    use settings::stream::ARROW_SIZE;
+
+    let rect = field.rect;
+    let max_len = field.samples.iter().fold(0.0, |a: f64, &dir| a.max(len(dir)));
+    let mut img = ImageBuffer::from_fn(rect[2] as u32, rect[3] as u32, |x, y| {
+        let pos = [rect[0] + x as f64 + 0.5, rect[1] + y as f64 + 0.5];
+        let dir = field.at(pos).unwrap_or([0.0; 2]);
+        let hue = dir[1].atan2(dir[0]);
+        let value = if max_len > 0.0 { len(dir) / max_len } else { 0.0 };
+        Rgb(hsv_to_rgb(hue, value))
+    });
+
+    for arrow in stream.arrows.iter() {
+        let line = arrow.line(speedup);
+        let (dx, dy) = (line[2] - line[0], line[3] - line[1]);
+        let l = (dx * dx + dy * dy).sqrt();
+        let mut lines = vec![line];
+        if l > 0.0 {
+            // Two strokes going back from the tip, like `Line::draw_arrow`.
+            let (ux, uy) = (dx / l * ARROW_SIZE, dy / l * ARROW_SIZE);
+            lines.push([line[2], line[3], line[2] - ux - uy, line[3] - uy + ux]);
+            lines.push([line[2], line[3], line[2] - ux + uy, line[3] - uy - ux]);
+        }
+        for line in lines.iter() {
+            draw_line(&mut img, [line[0] - rect[0], line[1] - rect[1],
+                line[2] - rect[0], line[3] - rect[1]], ARROW_COLOR);
+        }
+    }
+
+    img.save(path)
+}
+
+/// Draws a line by stepping one pixel at a time, clipped to the image.
+fn draw_line(img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, line: [f64; 4], color: [u8; 3]) {
+    let (dx, dy) = (line[2] - line[0], line[3] - line[1]);
+    let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as usize;
+    for k in 0..steps + 1 {
+        let t = k as f64 / steps as f64;
+        let (x, y) = (line[0] + dx * t, line[1] + dy * t);
+        if x < 0.0 || y < 0.0 { continue; }
+        let (x, y) = (x as u32, y as u32);
+        if x < img.width() && y < img.height() {
+            img.put_pixel(x, y, Rgb(color));
+        }
+    }
+}
+
+/// Converts a hue angle in radians, at full saturation, to a color.
+fn hsv_to_rgb(hue: f64, value: f64) -> [u8; 3] {
+    use std::f64::consts::PI;
+
+    let turns = hue / (2.0 * PI);
+    let h = (turns - turns.floor()) * 6.0;
+    let c = value;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
@@ -0,0 +1,116 @@
+//! A grid of stream samples
+//!
+//! The stream is sampled once per update at the grid corners,
+//! and read back in between by bilinear interpolation.
+
+/// Stream samples over a rectangle.
+#[derive(Clone)]
+pub struct FlowField {
+    pub rect: [f64; 4],
+    /// The distance between samples.
+    pub cell_size: f64,
+    /// The number of samples along each axis.
+    pub nm: [usize; 2],
+    /// Samples stored row by row.
+    pub samples: Vec<[f64; 2]>,
+}
+
+impl FlowField {
+    /// Samples a function over a rectangle, including its far edges.
+    ///
+    /// The cell size must be above zero.
+    pub fn bake<F>(rect: [f64; 4], cell_size: f64, f: F) -> FlowField
+        where F: Fn([f64; 2]) -> [f64; 2]
+    {
+        assert!(cell_size > 0.0, "cell size must be above zero, found {}", cell_size);
+        let n = ((rect[2] / cell_size).ceil() as usize).max(1) + 1;
+        let m = ((rect[3] / cell_size).ceil() as usize).max(1) + 1;
+        let mut samples = Vec::with_capacity(n * m);
+        for j in 0..m {
+            for i in 0..n {
+                samples.push(f([
+                    rect[0] + i as f64 * cell_size,
+                    rect[1] + j as f64 * cell_size
+                ]));
+            }
+        }
+        FlowField {
+            rect: rect,
+            cell_size: cell_size,
+            nm: [n, m],
+            samples: samples,
+        }
+    }
+
+    /// Interpolates the samples around a position,
+    /// or returns `None` outside the rectangle.
+    pub fn at(&self, pos: [f64; 2]) -> Option<[f64; 2]> {
+        let x = (pos[0] - self.rect[0]) / self.cell_size;
+        let y = (pos[1] - self.rect[1]) / self.cell_size;
+        let (n, m) = (self.nm[0], self.nm[1]);
+        if !(x >= 0.0 && y >= 0.0 && x <= (n - 1) as f64 && y <= (m - 1) as f64) {
+            return None;
+        }
+
+        let i = (x as usize).min(n - 2);
+        let j = (y as usize).min(m - 2);
+        let (tx, ty) = (x - i as f64, y - j as f64);
+        let a = self.samples[j * n + i];
+        let b = self.samples[j * n + i + 1];
+        let c = self.samples[(j + 1) * n + i];
+        let d = self.samples[(j + 1) * n + i + 1];
+        let mut res = [0.0; 2];
+        for k in 0..2 {
+            let top = a[k] + (b[k] - a[k]) * tx;
+            let bottom = c[k] + (d[k] - c[k]) * tx;
+            res[k] = top + (bottom - top) * ty;
+        }
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FlowField;
+
+    const RECT: [f64; 4] = [10.0, 20.0, 300.0, 200.0];
+
+    #[test]
+    fn linear_is_exact() {
+        let f = |pos: [f64; 2]| [2.0 * pos[0] - pos[1] + 3.0, 0.5 * pos[1]];
+        let field = FlowField::bake(RECT, 7.0, f);
+        for &pos in [[10.0, 20.0], [123.4, 56.7], [310.0, 220.0], [200.0, 219.9]].iter() {
+            let a = field.at(pos).unwrap();
+            let b = f(pos);
+            assert!((a[0] - b[0]).abs() < 1e-9 && (a[1] - b[1]).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn smooth_within_bound() {
+        // Bilinear interpolation is off by at most
+        // `h^2 / 8 * (max |f_xx| + max |f_yy|)` for a cell size `h`.
+        let (kx, ky) = (1.0 / 30.0, 1.0 / 20.0);
+        let f = |pos: [f64; 2]| [(pos[0] * kx).sin() * (pos[1] * ky).cos(), 0.0];
+        let h = 5.0;
+        let bound = h * h / 8.0 * (kx * kx + ky * ky);
+        let field = FlowField::bake(RECT, h, f);
+        for i in 0..100 {
+            for j in 0..100 {
+                let pos = [
+                    RECT[0] + RECT[2] * i as f64 / 99.0,
+                    RECT[1] + RECT[3] * j as f64 / 99.0
+                ];
+                let err = (field.at(pos).unwrap()[0] - f(pos)[0]).abs();
+                assert!(err <= bound, "error {} above {} at {:?}", err, bound, pos);
+            }
+        }
+    }
+
+    #[test]
+    fn outside() {
+        let field = FlowField::bake(RECT, 7.0, |_| [1.0, 1.0]);
+        assert!(field.at([9.0, 30.0]).is_none());
+        assert!(field.at([30.0, 230.0]).is_none());
+    }
+}
@@ -1,33 +1,32 @@
+use world::World;
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, RustcEncodable, RustcDecodable)]
 pub enum GameState {
     Play,
     Lose,
     Win,
 }
 
-pub fn should_update() -> bool {
-    use current_game_state;
-    
-    match unsafe { *current_game_state() } {
+pub fn should_update(w: &World) -> bool {
+    if w.editing { return false; }
+    match w.game_state {
         GameState::Play => true,
         _ => false
     }
 }
 
-pub fn update_game_state() {
-    use current_game_state;
-    use current_blood_bar;
+pub fn update_game_state(w: &mut World) {
     // use current_win_music;
     // use current_lose_music;
     use blood_bar::BloodBar;
 
-    let state = unsafe { &mut *current_game_state() };
-    let &mut BloodBar(blood_bar) = unsafe { &mut *current_blood_bar() };
+    if w.editing { return; }
+    let BloodBar(blood_bar) = w.blood_bar;
+    let has_won = won(w);
 
-    *state = match *state {
+    w.game_state = match w.game_state {
         GameState::Play => {
-            if won() {
+            if has_won {
                 // unsafe { current_win_music() }.play();
 
                 GameState::Win
@@ -43,13 +42,10 @@ pub fn update_game_state() {
     }
 }
 
-pub fn won() -> bool {
-    use current_player;
-    use settings::BEACH_ELLIPSE;
-
-    let player = unsafe { &mut *current_player() };
+pub fn won(w: &World) -> bool {
+    let player = &w.player;
     let (x, y) = (player.pos[0], player.pos[1]);
-    let beach = BEACH_ELLIPSE;
+    let beach = w.beach;
     let rw = 0.5 * beach[2];
     let rh = 0.5 * beach[3];
     let cx = beach[0] + rw;
@@ -62,4 +58,3 @@ pub fn won() -> bool {
         false
     }
 }
-
@@ -0,0 +1,114 @@
+//! Runs the simulation without a window
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use game::GameState;
+use player::{ self, KeyState };
+use replay::{ Playback, Replay };
+use world::World;
+
+/// A key change at a point in time.
+#[derive(Copy, Clone)]
+pub struct ScriptStep {
+    /// Seconds since start of the level.
+    pub time: f64,
+    /// Whether the key is pressed or released.
+    pub press: bool,
+    pub key: KeyState,
+}
+
+/// A scripted input sequence, sorted by time.
+pub struct Script {
+    pub steps: Vec<ScriptStep>,
+}
+
+impl Script {
+    /// Parses a script where each line reads `<time> press|release <key>`.
+    ///
+    /// Keys are `left`, `right`, `up` and `down`.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse(data: &str) -> Result<Script, String> {
+        let mut steps = Vec::new();
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.len() == 0 || line.starts_with("#") { continue; }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() != 3 {
+                return Err(format!("line {}: expected `<time> press|release <key>`",
+                    i + 1));
+            }
+            let time = match words[0].parse::<f64>() {
+                    Ok(x) if x.is_finite() => x,
+                    _ => {
+                        return Err(format!("line {}: invalid time `{}`",
+                            i + 1, words[0]));
+                    }
+                };
+            let press = match words[1] {
+                    "press" => true,
+                    "release" => false,
+                    x => {
+                        return Err(format!("line {}: expected press or release, found `{}`",
+                            i + 1, x));
+                    }
+                };
+            let key = match player::parse_key(words[2]) {
+                    Some(x) => x,
+                    None => {
+                        return Err(format!("line {}: unknown key `{}`",
+                            i + 1, words[2]));
+                    }
+                };
+            steps.push(ScriptStep { time: time, press: press, key: key });
+        }
+        // Times are finite, so they can always be compared.
+        steps.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        Ok(Script { steps: steps })
+    }
+
+    /// Reads a script from file.
+    pub fn from_path(path: &Path) -> Result<Script, String> {
+        let mut data = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut data)) {
+            Ok(_) => {}
+            Err(err) => {
+                return Err(format!("{}: {}", path.display(), err));
+            }
+        }
+        Script::parse(&data)
+    }
+}
+
+/// Advances the world with a fixed time step until the game is over
+/// or `duration` seconds have passed, then returns the final state.
+///
+/// The time step must be positive.
+pub fn run(w: &mut World, script: &Script, dt: f64, duration: f64) -> GameState {
+    let mut time = 0.0;
+    let mut next_step = 0;
+    while w.game_state == GameState::Play && time < duration {
+        while next_step < script.steps.len()
+            && script.steps[next_step].time <= time {
+            let step = script.steps[next_step];
+            if step.press {
+                w.player.key_state.insert(step.key);
+            } else {
+                w.player.key_state.remove(step.key);
+            }
+            next_step += 1;
+        }
+        w.update(dt);
+        time += dt;
+    }
+    w.game_state
+}
+
+/// Plays back every recorded input and returns the final state.
+pub fn play(w: &mut World, replay: &Replay) -> GameState {
+    let mut playback = Playback::new(replay.clone());
+    while playback.next_frame(w) {}
+    w.game_state
+}
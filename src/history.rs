@@ -0,0 +1,363 @@
+//! Undoing and redoing editor changes
+//!
+//! Every change is stored as a command that knows how to reverse itself.
+//! Changes made while an object follows the cursor
+//! are collected into one command when it is let go.
+
+use primitives::{ self, Primitives };
+use rocks::Rock;
+use sea_birds::SeaBird;
+use stream::{ self, Arrow, Oscillation };
+use world::World;
+
+/// The kind of object a command changes.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Target {
+    Arrows,
+    Rocks,
+    PalmTrees,
+    SeaBirds,
+    /// All primitives at once, which are never inserted or removed one by one.
+    Primitives,
+}
+
+/// A copy of one editable object.
+#[derive(Clone)]
+pub enum Object {
+    /// An arrow with its phase and oscillation.
+    Arrow(Arrow, f64, Oscillation),
+    Rock(Rock),
+    PalmTree([f64; 2]),
+    SeaBird(SeaBird),
+    Primitives(Primitives),
+}
+
+impl Object {
+    /// Whether the editor sees any difference between two objects.
+    ///
+    /// Sea birds keep flying while the game is played,
+    /// so only their target counts.
+    pub fn same(&self, other: &Object) -> bool {
+        match (self, other) {
+            (&Object::Arrow(a, a_phase, a_osc), &Object::Arrow(b, b_phase, b_osc)) =>
+                a == b && a_phase == b_phase && a_osc == b_osc,
+            (&Object::Rock(ref a), &Object::Rock(ref b)) => a == b,
+            (&Object::PalmTree(a), &Object::PalmTree(b)) => a == b,
+            (&Object::SeaBird(ref a), &Object::SeaBird(ref b)) => a.target == b.target,
+            (&Object::Primitives(ref a), &Object::Primitives(ref b)) => a == b,
+            _ => false
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Command {
+    Insert(usize, Object),
+    Remove(usize, Object),
+    /// The object at an index, before and after.
+    Replace(usize, Object, Object),
+}
+
+impl Command {
+    /// The command that takes this one back.
+    pub fn inverse(&self) -> Command {
+        match *self {
+            Command::Insert(id, ref object) => Command::Remove(id, object.clone()),
+            Command::Remove(id, ref object) => Command::Insert(id, object.clone()),
+            Command::Replace(id, ref before, ref after) =>
+                Command::Replace(id, after.clone(), before.clone()),
+        }
+    }
+}
+
+/// An object that follows the cursor, as it was when it was grabbed.
+struct Gesture {
+    target: Target,
+    id: usize,
+    /// `None` when the object was placed by the gesture.
+    before: Option<Object>,
+}
+
+pub struct History {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+    gesture: Option<Gesture>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            gesture: None,
+        }
+    }
+
+    /// Adds a command that is already done, and forgets what was undone.
+    pub fn push(&mut self, command: Command) {
+        self.remember(command);
+        self.redo.clear();
+    }
+
+    /// Forgets the oldest command beyond `settings::editor::HISTORY_SIZE`.
+    fn remember(&mut self, command: Command) {
+        use settings::editor::HISTORY_SIZE;
+
+        self.undo.push(command);
+        if self.undo.len() > HISTORY_SIZE {
+            self.undo.remove(0);
+        }
+    }
+}
+
+/// Copies an object out of the world.
+pub fn get(w: &World, target: Target, id: usize) -> Object {
+    match target {
+        Target::Arrows => Object::Arrow(w.stream.arrows[id],
+            w.stream.arrow_phases[id], w.stream.arrow_oscillations[id]),
+        Target::Rocks => Object::Rock(w.rocks.rocks[id].clone()),
+        Target::PalmTrees => Object::PalmTree(w.palm_trees.palms[id]),
+        Target::SeaBirds => Object::SeaBird(w.sea_birds.birds[id].clone()),
+        Target::Primitives => Object::Primitives(w.stream.primitives.clone()),
+    }
+}
+
+/// Changes the world as a command says.
+pub fn apply(w: &mut World, command: &Command) {
+    match *command {
+        Command::Insert(id, ref object) => match *object {
+            Object::Arrow(arrow, phase, oscillation) =>
+                w.stream.insert_arrow(id, arrow, phase, oscillation),
+            Object::Rock(ref rock) => {
+                w.rocks.rocks.insert(id, rock.clone());
+                stream::refresh_obstacles(w);
+            }
+            Object::PalmTree(pos) => w.palm_trees.palms.insert(id, pos),
+            Object::SeaBird(ref bird) => w.sea_birds.birds.insert(id, bird.clone()),
+            Object::Primitives(_) => unreachable!()
+        },
+        Command::Remove(id, ref object) => match *object {
+            Object::Arrow(..) => w.stream.remove_arrow(id),
+            Object::Rock(_) => {
+                w.rocks.rocks.remove(id);
+                stream::refresh_obstacles(w);
+            }
+            Object::PalmTree(_) => { w.palm_trees.palms.remove(id); }
+            Object::SeaBird(_) => { w.sea_birds.birds.remove(id); }
+            Object::Primitives(_) => unreachable!()
+        },
+        Command::Replace(id, _, ref object) => match *object {
+            Object::Arrow(arrow, phase, oscillation) =>
+                w.stream.set_arrow(id, arrow, phase, oscillation),
+            Object::Rock(ref rock) => {
+                w.rocks.rocks[id] = rock.clone();
+                stream::refresh_obstacles(w);
+            }
+            Object::PalmTree(pos) => w.palm_trees.palms[id] = pos,
+            // Keeps the bird where it is flying now.
+            Object::SeaBird(ref bird) => w.sea_birds.birds[id].target = bird.target,
+            Object::Primitives(ref primitives) =>
+                w.stream.set_primitives(primitives.clone()),
+        },
+    }
+    stream::refresh_moving_arrows(w);
+}
+
+/// Applies a command and remembers it.
+pub fn run(w: &mut World, command: Command) {
+    apply(w, &command);
+    w.editor.history.push(command);
+}
+
+/// Starts collecting changes to an object into one command.
+///
+/// Set `placed` when the object was just put into the world.
+/// Finishes the previous gesture, if any.
+pub fn begin(w: &mut World, target: Target, id: usize, placed: bool) {
+    end(w);
+    let before = if placed { None } else { Some(get(w, target, id)) };
+    w.editor.history.gesture = Some(Gesture {
+        target: target,
+        id: id,
+        before: before,
+    });
+}
+
+/// Remembers the changes since `begin`, unless nothing changed.
+pub fn end(w: &mut World) {
+    let gesture = match w.editor.history.gesture.take() {
+        None => { return; }
+        Some(x) => x
+    };
+    let after = get(w, gesture.target, gesture.id);
+    let command = match gesture.before {
+        None => Command::Insert(gesture.id, after),
+        Some(ref before) if before.same(&after) => { return; }
+        Some(before) => Command::Replace(gesture.id, before, after),
+    };
+    w.editor.history.push(command);
+}
+
+/// Lets go of the objects following the cursor,
+/// since their indices might not be valid after undo or redo.
+fn let_go(w: &mut World) {
+    end(w);
+    w.editor.grabbed = None;
+    stream::deselect_arrow(w);
+    primitives::deselect_primitive(w);
+}
+
+/// Takes back the last change.
+pub fn undo(w: &mut World) {
+    let_go(w);
+    let command = match w.editor.history.undo.pop() {
+        None => { return; }
+        Some(x) => x
+    };
+    apply(w, &command.inverse());
+    w.editor.history.redo.push(command);
+}
+
+/// Does the last undone change again.
+pub fn redo(w: &mut World) {
+    let_go(w);
+    let command = match w.editor.history.redo.pop() {
+        None => { return; }
+        Some(x) => x
+    };
+    apply(w, &command);
+    w.editor.history.remember(command);
+}
+
+/// Forgets every change, such as when the world is replaced by a snapshot.
+pub fn clear(w: &mut World) {
+    w.editor.history = History::new();
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use editor::{ self, Tool };
+    use level::{ Level, LevelArrow };
+    use stream::{ self, Arrow, Oscillation, Waveform };
+    use world::World;
+    use super::{ get, redo, run, undo, Command, Object, Target };
+
+    fn world() -> World {
+        let pulse = Oscillation {
+            frequency: 0.5,
+            amplitude: 2.0,
+            offset: 0.5,
+            waveform: Waveform::Pulse,
+        };
+        let level = Level {
+            seed: None,
+            beach: [500.0, -500.0, 1000.0, 1500.0],
+            start_pos: [100.0, 100.0],
+            blood_bar_start: 1.0,
+            strength: [10.0, 50.0],
+            arrows: vec![
+                LevelArrow { pos: [100.0, 300.0], dir: [30.0, 10.0], phase: 0.0,
+                    oscillation: None },
+                LevelArrow { pos: [300.0, 200.0], dir: [-20.0, 25.0], phase: 0.3,
+                    oscillation: Some(pulse) },
+                LevelArrow { pos: [500.0, 100.0], dir: [0.0, 40.0], phase: 1.2,
+                    oscillation: None },
+            ],
+            kernel: None,
+            model: None,
+            primitives: None,
+            generate: None,
+            rocks: vec![[400.0, 400.0]],
+            palm_trees: vec![[0.0, 0.0]],
+            sea_birds: Vec::new(),
+            config: None,
+        };
+        let mut w = World::new(&level, Config::new(), [0.0, 0.0, 640.0, 480.0], 1);
+        w.editing = true;
+        w
+    }
+
+    fn arrows(w: &World) -> (Vec<Arrow>, Vec<f64>, Vec<Oscillation>) {
+        (w.stream.arrows.clone(), w.stream.arrow_phases.clone(),
+            w.stream.arrow_oscillations.clone())
+    }
+
+    #[test]
+    fn undo_redo_arrows() {
+        let mut w = world();
+        let mut states = vec![arrows(&w)];
+
+        let arrow = Arrow { pos: [200.0, 250.0], dir: [5.0, -5.0] };
+        run(&mut w, Command::Insert(1, Object::Arrow(arrow, 0.7, Oscillation::new())));
+        states.push(arrows(&w));
+        editor::change_arrow(&mut w, [300.0, 200.0], stream::cycle_waveform);
+        states.push(arrows(&w));
+        editor::change_arrow(&mut w, [500.0, 100.0], |w, pos| stream::shift_phase(w, pos, 0.25));
+        states.push(arrows(&w));
+        let object = get(&w, Target::Arrows, 0);
+        run(&mut w, Command::Remove(0, object));
+        states.push(arrows(&w));
+
+        for state in states.iter().rev().skip(1) {
+            undo(&mut w);
+            assert!(arrows(&w) == *state);
+        }
+        for state in states.iter().skip(1) {
+            redo(&mut w);
+            assert!(arrows(&w) == *state);
+        }
+    }
+
+    #[test]
+    fn gesture_is_one_step() {
+        let mut w = world();
+        let before = arrows(&w);
+
+        editor::select_tool(&mut w, Tool::Arrows);
+        editor::add_arrow(&mut w, [200.0, 200.0]);
+        for i in 0..10 {
+            stream::edit_selected_arrow(&mut w, [210.0 + i as f64, 190.0]);
+        }
+        editor::release(&mut w);
+        assert_eq!(w.stream.arrows.len(), 4);
+
+        editor::select_tool(&mut w, Tool::Rocks);
+        editor::grab(&mut w, [400.0, 400.0]);
+        for i in 0..10 {
+            editor::drag(&mut w, [400.0 + i as f64 * 5.0, 400.0]);
+        }
+        editor::release(&mut w);
+        assert_eq!(w.rocks.rocks[0].pos, [445.0, 400.0]);
+
+        undo(&mut w);
+        assert_eq!(w.rocks.rocks[0].pos, [400.0, 400.0]);
+        undo(&mut w);
+        assert!(arrows(&w) == before);
+        // Nothing else to undo.
+        undo(&mut w);
+        assert!(arrows(&w) == before);
+        assert_eq!(w.rocks.rocks[0].pos, [400.0, 400.0]);
+    }
+
+    #[test]
+    fn oldest_dropped() {
+        use settings::editor::HISTORY_SIZE;
+
+        let mut w = world();
+        for i in 0..HISTORY_SIZE + 1 {
+            let before = Object::PalmTree(w.palm_trees.palms[0]);
+            let after = Object::PalmTree([i as f64 + 1.0, 0.0]);
+            run(&mut w, Command::Replace(0, before, after));
+        }
+        for _ in 0..HISTORY_SIZE + 5 {
+            undo(&mut w);
+        }
+        // The first change is forgotten.
+        assert_eq!(w.palm_trees.palms[0], [1.0, 0.0]);
+        for _ in 0..HISTORY_SIZE {
+            redo(&mut w);
+        }
+        assert_eq!(w.palm_trees.palms[0], [HISTORY_SIZE as f64 + 1.0, 0.0]);
+    }
+}
@@ -0,0 +1,99 @@
+//! Moves things along the sea current
+
+/// How a position is stepped through a velocity field.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Scheme {
+    /// One sample at the start, drifts at low frame rates.
+    Euler,
+    /// Two samples, the second halfway through the step.
+    Midpoint,
+    /// Four samples, fourth order Runge-Kutta.
+    Rk4,
+}
+
+/// The names used in the config file.
+pub static SCHEMES: &'static [(&'static str, Scheme)] = &[
+    ("euler", Scheme::Euler),
+    ("midpoint", Scheme::Midpoint),
+    ("rk4", Scheme::Rk4),
+];
+
+pub fn parse_scheme(name: &str) -> Option<Scheme> {
+    SCHEMES.iter().find(|&&(x, _)| x == name).map(|&(_, scheme)| scheme)
+}
+
+pub fn scheme_name(scheme: Scheme) -> &'static str {
+    SCHEMES.iter().find(|&&(_, x)| x == scheme).map(|&(name, _)| name).unwrap()
+}
+
+/// Returns the position after moving `dt` seconds with velocity `f`.
+pub fn advect<F>(scheme: Scheme, pos: [f64; 2], dt: f64, f: F) -> [f64; 2]
+    where F: Fn([f64; 2]) -> [f64; 2]
+{
+    use vecmath::vec2_add as add;
+    use vecmath::vec2_scale as scale;
+
+    match scheme {
+        Scheme::Euler => add(pos, scale(f(pos), dt)),
+        Scheme::Midpoint => {
+            let k1 = f(pos);
+            let k2 = f(add(pos, scale(k1, 0.5 * dt)));
+            add(pos, scale(k2, dt))
+        }
+        Scheme::Rk4 => {
+            let k1 = f(pos);
+            let k2 = f(add(pos, scale(k1, 0.5 * dt)));
+            let k3 = f(add(pos, scale(k2, 0.5 * dt)));
+            let k4 = f(add(pos, scale(k3, dt)));
+            let sum = add(add(k1, scale(k2, 2.0)), add(scale(k3, 2.0), k4));
+            add(pos, scale(sum, dt / 6.0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ advect, Scheme };
+
+    /// Turns around the origin once every `2 * PI` seconds.
+    fn spin(pos: [f64; 2]) -> [f64; 2] {
+        [-pos[1], pos[0]]
+    }
+
+    /// The distance from the exact position after one second.
+    fn error(scheme: Scheme, steps: usize) -> f64 {
+        let dt = 1.0 / steps as f64;
+        let mut pos = [1.0, 0.0];
+        for _ in 0..steps {
+            pos = advect(scheme, pos, dt, spin);
+        }
+        let exact = [1.0f64.cos(), 1.0f64.sin()];
+        ((pos[0] - exact[0]).powi(2) + (pos[1] - exact[1]).powi(2)).sqrt()
+    }
+
+    #[test]
+    fn euler_step() {
+        let pos = advect(Scheme::Euler, [2.0, 3.0], 0.5, spin);
+        assert_eq!(pos, [0.5, 4.0]);
+    }
+
+    #[test]
+    fn order() {
+        // Halving the step divides the error by about `2^order`.
+        for &(scheme, order) in [
+            (Scheme::Euler, 1),
+            (Scheme::Midpoint, 2),
+            (Scheme::Rk4, 4)
+        ].iter() {
+            let ratio = error(scheme, 32) / error(scheme, 64);
+            let expected = 2.0f64.powi(order);
+            assert!(ratio > 0.9 * expected && ratio < 1.1 * expected,
+                "{:?} ratio {}, expected {}", scheme, ratio, expected);
+        }
+    }
+
+    #[test]
+    fn rk4_error() {
+        assert!(error(Scheme::Rk4, 60) < 1e-8);
+    }
+}
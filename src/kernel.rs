@@ -0,0 +1,96 @@
+//! How the arrows are blended into the sea current
+
+/// How the weight of an arrow falls off with distance.
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable)]
+pub enum Falloff {
+    /// Weighted by one over the distance raised to a power.
+    InverseDistance(f64),
+    /// Weighted by a Gaussian with a radius.
+    Gaussian(f64),
+    /// Follows the closest arrow only.
+    Nearest,
+}
+
+/// Weighs arrows by their distance from a position.
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable)]
+pub struct Kernel {
+    pub falloff: Falloff,
+    /// Arrows further away do not contribute.
+    /// The current is calm where no arrow is close enough.
+    pub cutoff: Option<f64>,
+}
+
+impl Kernel {
+    /// Inverse square distance without cutoff.
+    pub fn new() -> Kernel {
+        Kernel {
+            falloff: Falloff::InverseDistance(2.0),
+            cutoff: None,
+        }
+    }
+
+    /// Checks for values that would make the current infinite or not a number.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.falloff {
+            Falloff::InverseDistance(power) if !(power >= 0.0) || !power.is_finite() => {
+                return Err(format!("`InverseDistance` power must not be negative, found `{}`",
+                    power));
+            }
+            Falloff::Gaussian(radius) if !(radius > 0.0) || !radius.is_finite() => {
+                return Err(format!("`Gaussian` radius must be above zero, found `{}`",
+                    radius));
+            }
+            _ => {}
+        }
+        match self.cutoff {
+            Some(cutoff) if !(cutoff >= 0.0) => {
+                Err(format!("`cutoff` must not be negative, found `{}`", cutoff))
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Returns `true` if arrows at this distance contribute.
+    pub fn reaches(&self, dist: f64) -> bool {
+        match self.cutoff {
+            None => true,
+            Some(cutoff) => dist <= cutoff
+        }
+    }
+
+    /// The weight of an arrow at a distance.
+    pub fn weight(&self, dist: f64) -> f64 {
+        if !self.reaches(dist) { return 0.0; }
+        match self.falloff {
+            Falloff::InverseDistance(power) => 1.0 / dist.max(0.01).powf(power),
+            Falloff::Gaussian(radius) => {
+                let x = dist / radius;
+                (-x * x).exp()
+            }
+            Falloff::Nearest => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ Falloff, Kernel };
+
+    fn kernel(falloff: Falloff, cutoff: Option<f64>) -> Kernel {
+        Kernel { falloff: falloff, cutoff: cutoff }
+    }
+
+    #[test]
+    fn validate() {
+        use std::f64::{ INFINITY, NAN };
+
+        assert!(Kernel::new().validate().is_ok());
+        assert!(kernel(Falloff::Gaussian(80.0), Some(200.0)).validate().is_ok());
+        assert!(kernel(Falloff::Nearest, Some(0.0)).validate().is_ok());
+        assert!(kernel(Falloff::InverseDistance(-1.0), None).validate().is_err());
+        assert!(kernel(Falloff::InverseDistance(INFINITY), None).validate().is_err());
+        assert!(kernel(Falloff::Gaussian(0.0), None).validate().is_err());
+        assert!(kernel(Falloff::Gaussian(NAN), None).validate().is_err());
+        assert!(kernel(Falloff::Nearest, Some(-1.0)).validate().is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Imports the comma separated asset files used before level files
+//!
+//! Each file is a flat list of numbers separated by commas,
+//! read in records of a fixed size, for example `x, y, dx, dy, phase`
+//! for the stream. Line breaks separate numbers too,
+//! so trailing commas are allowed, and `#` or `//` starts a comment.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{ self, Read };
+use std::path::{ Path, PathBuf };
+
+use level::{ Level, LevelArrow };
+
+pub const BEACH_ELLIPSE: [f64; 4] = [500.0, -500.0, 1000.0, 1500.0];
+pub const START_POS: [f64; 2] = [100.0, 100.0];
+pub const BLOOD_BAR_START: f64 = 1.0;
+pub const STRENGTH: [f64; 2] = [10.0, 50.0];
+
+/// What went wrong when parsing a legacy file.
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    /// The file could not be read.
+    Io(io::Error),
+    /// Nothing before a comma.
+    EmptyValue,
+    /// A value that is not a number.
+    InvalidNumber(String),
+    /// The file ended in the middle of a record.
+    IncompleteRecord {
+        expected: usize,
+        found: usize,
+    },
+}
+
+/// An error with the location in the legacy file.
+#[derive(Debug)]
+pub struct ParseError {
+    pub file: PathBuf,
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// Column number in characters, starting at 1.
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}:{}:{}: ", self.file.display(), self.line, self.column));
+        match self.kind {
+            ParseErrorKind::Io(ref err) => write!(f, "{}", err),
+            ParseErrorKind::EmptyValue => write!(f, "expected number before comma"),
+            ParseErrorKind::InvalidNumber(ref x) =>
+                write!(f, "expected number, found `{}`", x),
+            ParseErrorKind::IncompleteRecord { expected, found } =>
+                write!(f, "expected {} numbers in last record, found {}",
+                    expected, found),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        match self.kind {
+            ParseErrorKind::Io(_) => "could not read legacy file",
+            ParseErrorKind::EmptyValue => "empty value",
+            ParseErrorKind::InvalidNumber(_) => "invalid number",
+            ParseErrorKind::IncompleteRecord { .. } => "incomplete record",
+        }
+    }
+}
+
+/// Splits the data into records of `fields` numbers each.
+pub fn parse(file: &Path, data: &str, fields: usize)
+-> Result<Vec<Vec<f64>>, ParseError> {
+    let error = |line: usize, column: usize, kind: ParseErrorKind| {
+        ParseError {
+            file: file.to_path_buf(),
+            line: line,
+            column: column,
+            kind: kind,
+        }
+    };
+
+    let mut records = Vec::new();
+    let mut record = Vec::with_capacity(fields);
+    // Location of the first number in the current record.
+    let mut record_start = (1, 1);
+    for (i, line) in data.lines().enumerate() {
+        let line = match line.find("//") {
+            None => line,
+            Some(end) => &line[..end]
+        };
+        let line = match line.find("#") {
+            None => line,
+            Some(end) => &line[..end]
+        };
+        let values: Vec<&str> = line.split(",").collect();
+        let mut offset = 0;
+        for (j, value) in values.iter().enumerate() {
+            let column = line[..offset].chars().count() + 1;
+            offset += value.len() + 1;
+            let trimmed = value.trim();
+            if trimmed.len() == 0 {
+                // A trailing comma or a blank line is fine,
+                // but not an empty value before a comma.
+                if j + 1 == values.len() { continue; }
+                return Err(error(i + 1, column, ParseErrorKind::EmptyValue));
+            }
+            let column = column + value.chars().take_while(|c| c.is_whitespace()).count();
+            let x: f64 = match trimmed.parse() {
+                    Ok(x) => x,
+                    Err(_) => {
+                        return Err(error(i + 1, column,
+                            ParseErrorKind::InvalidNumber(trimmed.to_string())));
+                    }
+                };
+            if record.len() == 0 { record_start = (i + 1, column); }
+            record.push(x);
+            if record.len() == fields {
+                records.push(record);
+                record = Vec::with_capacity(fields);
+            }
+        }
+    }
+    if record.len() > 0 {
+        let (line, column) = record_start;
+        return Err(error(line, column, ParseErrorKind::IncompleteRecord {
+            expected: fields,
+            found: record.len(),
+        }));
+    }
+    Ok(records)
+}
+
+/// Reads a legacy file and splits it into records.
+pub fn parse_file(path: &Path, fields: usize) -> Result<Vec<Vec<f64>>, ParseError> {
+    let mut data = String::new();
+    match File::open(path).and_then(|mut f| f.read_to_string(&mut data)) {
+        Ok(_) => {}
+        Err(err) => {
+            return Err(ParseError {
+                file: path.to_path_buf(),
+                line: 1,
+                column: 1,
+                kind: ParseErrorKind::Io(err),
+            });
+        }
+    }
+    parse(path, &data, fields)
+}
+
+/// Builds a level from the legacy asset files in a folder.
+pub fn level(dir: &Path) -> Result<Level, ParseError> {
+    let mut level = Level {
+        seed: None,
+        beach: BEACH_ELLIPSE,
+        start_pos: START_POS,
+        blood_bar_start: BLOOD_BAR_START,
+        strength: STRENGTH,
+        arrows: Vec::new(),
+        kernel: None,
+        model: None,
+        primitives: None,
+        generate: None,
+        rocks: Vec::new(),
+        palm_trees: Vec::new(),
+        sea_birds: Vec::new(),
+        config: None,
+    };
+    for r in try!(parse_file(&dir.join("stream.txt"), 5)).iter() {
+        level.arrows.push(LevelArrow {
+            pos: [r[0], r[1]],
+            dir: [r[2], r[3]],
+            phase: r[4],
+            oscillation: None,
+        });
+    }
+    for r in try!(parse_file(&dir.join("rocks.txt"), 2)).iter() {
+        level.rocks.push([r[0], r[1]]);
+    }
+    for r in try!(parse_file(&dir.join("palm_trees.txt"), 2)).iter() {
+        level.palm_trees.push([r[0], r[1]]);
+    }
+    for r in try!(parse_file(&dir.join("sea_birds.txt"), 2)).iter() {
+        level.sea_birds.push([r[0], r[1]]);
+    }
+    Ok(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{ parse, ParseError, ParseErrorKind };
+
+    fn parse_err(data: &str, fields: usize) -> ParseError {
+        match parse(Path::new("test.txt"), data, fields) {
+            Ok(_) => panic!("expected an error for `{}`", data),
+            Err(err) => err
+        }
+    }
+
+    #[test]
+    fn comments() {
+        let records = parse(Path::new("test.txt"),
+            "# stream\n1, 2 // first\n3, 4 # second\n", 2).unwrap();
+        assert_eq!(records, vec![vec![1.0, 2.0], vec![3.0, 4.0]]);
+    }
+
+    #[test]
+    fn trailing_comma() {
+        let records = parse(Path::new("test.txt"), "1, 2,\n\n3, 4,\n", 2).unwrap();
+        assert_eq!(records, vec![vec![1.0, 2.0], vec![3.0, 4.0]]);
+    }
+
+    #[test]
+    fn bad_number() {
+        let err = parse_err("1, 2,\n3, x,", 2);
+        assert_eq!((err.line, err.column), (2, 4));
+        match err.kind {
+            ParseErrorKind::InvalidNumber(ref x) => assert_eq!(x, "x"),
+            ref x => panic!("unexpected {:?}", x)
+        }
+    }
+
+    #[test]
+    fn empty_value() {
+        let err = parse_err("1,,2", 2);
+        assert_eq!((err.line, err.column), (1, 3));
+        match err.kind {
+            ParseErrorKind::EmptyValue => {}
+            ref x => panic!("unexpected {:?}", x)
+        }
+    }
+
+    #[test]
+    fn leading_empty_value() {
+        let err = parse_err("1, 2,\n,3, 4", 2);
+        assert_eq!((err.line, err.column), (2, 1));
+        match err.kind {
+            ParseErrorKind::EmptyValue => {}
+            ref x => panic!("unexpected {:?}", x)
+        }
+    }
+
+    #[test]
+    fn wrong_field_count() {
+        let err = parse_err("1, 2, 3,\n4, 5", 3);
+        assert_eq!((err.line, err.column), (2, 1));
+        match err.kind {
+            ParseErrorKind::IncompleteRecord { expected, found } =>
+                assert_eq!((expected, found), (3, 2)),
+            ref x => panic!("unexpected {:?}", x)
+        }
+    }
+}
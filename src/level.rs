@@ -0,0 +1,147 @@
+//! Level data loaded at runtime
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{ self, Read, Write };
+use std::path::{ Path, PathBuf };
+use rustc_serialize::json;
+
+use kernel::Kernel;
+use primitives::Primitives;
+use stream::{ FieldModel, Oscillation };
+use stream_gen::StreamGen;
+
+/// A sea current arrow as stored in level files.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct LevelArrow {
+    pub pos: [f64; 2],
+    pub dir: [f64; 2],
+    /// The phase of the arrow when the level starts.
+    pub phase: f64,
+    /// How the arrow strength changes, a sine wave if left out.
+    pub oscillation: Option<Oscillation>,
+}
+
+/// Describes everything that differs from one level to another.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct Level {
+    /// Seed for random decisions, unless one is given on the command line.
+    pub seed: Option<u32>,
+    /// The ellipse of land the player must reach.
+    pub beach: [f64; 4],
+    /// Where the player starts swimming.
+    pub start_pos: [f64; 2],
+    /// How full the blood bar is at start, between 0 and 1.
+    pub blood_bar_start: f64,
+    /// A range to pick random sea current strength.
+    pub strength: [f64; 2],
+    pub arrows: Vec<LevelArrow>,
+    /// How arrows are blended, inverse square distance if left out.
+    pub kernel: Option<Kernel>,
+    /// How the arrows make up the current, a weighted average if left out.
+    pub model: Option<FieldModel>,
+    /// Whirlpools, outflows and jets.
+    pub primitives: Option<Primitives>,
+    /// Generates arrows in addition to those listed.
+    pub generate: Option<StreamGen>,
+    pub rocks: Vec<[f64; 2]>,
+    pub palm_trees: Vec<[f64; 2]>,
+    /// The positions sea birds circle around.
+    pub sea_birds: Vec<[f64; 2]>,
+    /// Tuning values for this level, on top of the config file.
+    pub config: Option<BTreeMap<String, f64>>,
+}
+
+/// An error when reading or writing a level or snapshot file.
+#[derive(Debug)]
+pub enum LevelError {
+    Io(PathBuf, io::Error),
+    Decode(PathBuf, json::DecoderError),
+    /// A value that the game can not play with.
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LevelError::Io(ref path, ref err) =>
+                write!(f, "{}: {}", path.display(), err),
+            LevelError::Decode(ref path, ref err) =>
+                write!(f, "{}: {}", path.display(), err),
+            LevelError::Invalid(ref path, ref err) =>
+                write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for LevelError {
+    fn description(&self) -> &str {
+        match *self {
+            LevelError::Io(_, _) => "could not access file",
+            LevelError::Decode(_, _) => "invalid file",
+            LevelError::Invalid(_, _) => "invalid level",
+        }
+    }
+}
+
+impl Level {
+    /// Reads a level from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Level, LevelError> {
+        let mut data = String::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_string(&mut data))
+            .map_err(|err| LevelError::Io(path.to_path_buf(), err)));
+        let level: Level = try!(json::decode(&data)
+            .map_err(|err| LevelError::Decode(path.to_path_buf(), err)));
+        try!(level.validate().map_err(|err| LevelError::Invalid(path.to_path_buf(), err)));
+        Ok(level)
+    }
+
+    /// Checks for values that would break the simulation.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref kernel) = self.kernel {
+            try!(kernel.validate().map_err(|err| format!("kernel: {}", err)));
+        }
+        if let Some(ref gen) = self.generate {
+            try!(gen.validate().map_err(|err| format!("generate: {}", err)));
+        }
+        Ok(())
+    }
+
+    /// Writes the level to a JSON file.
+    pub fn save(&self, path: &Path) -> Result<(), LevelError> {
+        let data = format!("{}\n", json::as_pretty_json(self));
+        File::create(path).and_then(|mut f| f.write_all(data.as_bytes()))
+            .map_err(|err| LevelError::Io(path.to_path_buf(), err))
+    }
+
+    /// Writes the level over a file, keeping the old one as `<file>.bak`.
+    ///
+    /// The level goes into `<file>.tmp` first and is then renamed,
+    /// so a failed save never leaves a half written level behind.
+    pub fn replace(&self, path: &Path) -> Result<(), LevelError> {
+        use std::ffi::OsString;
+        use std::fs;
+
+        let with_suffix = |suffix: &str| {
+            let mut name = OsString::from(path.as_os_str());
+            name.push(suffix);
+            PathBuf::from(name)
+        };
+        let tmp = with_suffix(".tmp");
+        try!(self.save(&tmp));
+        if fs::metadata(path).is_ok() {
+            let backup = with_suffix(".bak");
+            try!(fs::copy(path, &backup)
+                .map_err(|err| LevelError::Io(backup.clone(), err)));
+        }
+        fs::rename(&tmp, path)
+            .map_err(|err| LevelError::Io(path.to_path_buf(), err))
+    }
+
+    /// The values the level overrides, if any.
+    pub fn overrides(&self) -> BTreeMap<String, f64> {
+        self.config.clone().unwrap_or(BTreeMap::new())
+    }
+}
@@ -5,42 +5,378 @@ extern crate opengl_graphics;
 extern crate vecmath;
 extern crate ai_behavior;
 extern crate interpolation;
-extern crate current;
 extern crate sdl2;
 extern crate rand;
+extern crate rustc_serialize;
+extern crate image;
 #[macro_use]
 extern crate bitflags;
 
-use current::{ Current, CurrentGuard };
 // use sdl2_mixer as mix;
 use std::path::*;
 
+mod arrow_tree;
 mod blood;
 mod blood_bar;
+mod bindings;
+mod campaign;
+mod config;
+mod editor;
+mod export;
+mod flow_field;
 mod game;
+mod headless;
+mod history;
+mod integrator;
+mod kernel;
+mod legacy;
+mod level;
+mod obstacles;
 mod palm_trees;
 mod player;
+mod primitives;
 mod render;
+mod replay;
 mod rocks;
 mod settings;
+mod snapshot;
 mod stream;
+mod stream_gen;
 mod sea_birds;
+mod world;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(path) = flag_value(&args, "--import-legacy") {
+        import_legacy(&args, Path::new(path));
+        return;
+    }
+    if let Some(path) = flag_value(&args, "--export-field") {
+        export_field(&args, Path::new(path));
+        return;
+    }
+    if has_flag(&args, "--headless") {
+        run_headless(&args);
+        return;
+    }
+
+    // Replays start from the level, so they could not reproduce a snapshot.
+    if has_flag(&args, "--snapshot")
+    && (has_flag(&args, "--record") || has_flag(&args, "--replay")) {
+        println!("--snapshot can not be used with --record or --replay");
+        std::process::exit(1);
+    }
+
+    let bindings = load_bindings(&args);
+    let playback = load_replay(&args);
+    let mut campaign = match playback {
+        Some(ref replay) => campaign::Campaign::single(&replay.level),
+        None => load_campaign(&args)
+    };
+
+    let mut run = 0;
     let opengl = opengl_graphics::OpenGL::_3_2;
     start_piston::start(
         opengl,
         piston::window::WindowSettings::new(
-            "Sea Birds' Breakfast", [640, 480]
+            "Sea Birds' Breakfast", settings::WINDOW_SIZE
         )
             .samples(4)
             .fullscreen(false)
             .exit_on_esc(true)
         ,
-        &mut || load_assets(&mut || setup())
+        &mut || load_assets(&mut |textures| {
+            run += 1;
+            setup(textures, &args, &bindings, &mut campaign, playback.as_ref(), run)
+        })
     );
 }
 
+fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter().any(|arg| *arg == flag)
+}
+
+/// Returns the value following a command line flag.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    match args.iter().position(|arg| *arg == flag) {
+        None => None,
+        Some(i) => args.get(i + 1).map(|x| &x[..])
+    }
+}
+
+/// Parses the value following a command line flag, or exits.
+fn parse_flag<T: std::str::FromStr>(args: &[String], flag: &str, default: T) -> T {
+    match flag_value(args, flag) {
+        None => default,
+        Some(x) => match x.parse() {
+            Ok(x) => x,
+            Err(_) => {
+                println!("Invalid value for {}: `{}`", flag, x);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Parses a number following a command line flag that must be above zero, or exits.
+fn parse_positive_flag(args: &[String], flag: &str, default: f64) -> f64 {
+    let x = parse_flag(args, flag, default);
+    if !(x > 0.0) || !x.is_finite() {
+        println!("Invalid value for {}: `{}`", flag, x);
+        std::process::exit(1);
+    }
+    x
+}
+
+/// Parses a number following a command line flag that must be finite, or exits.
+fn parse_finite_flag(args: &[String], flag: &str, default: f64) -> f64 {
+    let x = parse_flag(args, flag, default);
+    if !x.is_finite() {
+        println!("Invalid value for {}: `{}`", flag, x);
+        std::process::exit(1);
+    }
+    x
+}
+
+/// Plays the single level given by `--level <file>`,
+/// or the campaign from where the player left off.
+fn load_campaign(args: &[String]) -> campaign::Campaign {
+    use campaign::Campaign;
+
+    if let Some(x) = flag_value(args, "--level") {
+        return Campaign::single(Path::new(x));
+    }
+    match Campaign::from_path(&root().join(settings::campaign::LEVELS),
+        &root().join(settings::campaign::PROGRESS)) {
+        Ok(x) => x,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads the current level of the campaign, or exits.
+fn load_level(campaign: &campaign::Campaign) -> level::Level {
+    match campaign.level() {
+        Ok(x) => x,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The config file given by `--config <file>`, or the default one.
+fn config_path(args: &[String]) -> PathBuf {
+    match flag_value(args, "--config") {
+        None => root().join(settings::config::FILE),
+        Some(x) => PathBuf::from(x)
+    }
+}
+
+/// Reads the tuning values with the overrides of a level, or exits.
+fn load_config(args: &[String], level: &level::Level) -> config::Config {
+    let config = config::Config::from_path(&config_path(args))
+        .and_then(|config| config.with_overrides(&level.overrides()));
+    match config {
+        Ok(x) => x,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Uses the tuning values a replay was recorded with,
+/// telling when they differ from the current config.
+///
+/// Older replays without values use the current config.
+fn replay_config(args: &[String], level: &level::Level, replay: &replay::Replay)
+-> config::Config {
+    let config = load_config(args, level);
+    match replay.config {
+        None => config,
+        Some(ref recorded) => {
+            if recorded.to_string() != config.to_string() {
+                println!("The config differs from the one the replay was recorded with, \
+                    using the recorded values");
+            }
+            recorded.clone()
+        }
+    }
+}
+
+/// Reads the bindings given by `--bindings <file>`, or the user's bindings.
+///
+/// The user's bindings file is created with the defaults the first time.
+fn load_bindings(args: &[String]) -> bindings::Bindings {
+    use bindings::Bindings;
+
+    let path = match flag_value(args, "--bindings") {
+        None => root().join(settings::bindings::FILE),
+        Some(x) => PathBuf::from(x)
+    };
+    if !path.exists() {
+        let bindings = Bindings::new();
+        match bindings.save(&path) {
+            Ok(()) => println!("Saved default bindings to {}", path.display()),
+            Err(err) => println!("Could not save bindings: {}: {}", path.display(), err)
+        }
+        return bindings;
+    }
+    match Bindings::from_path(&path) {
+        Ok(x) => x,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads the replay given by `--replay <file>`, or exits.
+fn load_replay(args: &[String]) -> Option<replay::Replay> {
+    match flag_value(args, "--replay") {
+        None => None,
+        Some(path) => match replay::Replay::from_path(Path::new(path)) {
+            Ok(x) => Some(x),
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// The file for a run of the game, counting from 1.
+///
+/// The first run goes to `path`, later ones to `<name>-<run>.<extension>`.
+fn recording_path(path: &Path, run: u32) -> PathBuf {
+    if run <= 1 { return path.to_path_buf(); }
+    let stem = path.file_stem().map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or(String::new());
+    let name = match path.extension() {
+        None => format!("{}-{}", stem, run),
+        Some(ext) => format!("{}-{}.{}", stem, run, ext.to_string_lossy())
+    };
+    path.with_file_name(name)
+}
+
+/// Writes the recorded inputs to `--record <file>`,
+/// with one file per run so restarts do not overwrite earlier runs.
+fn save_recording(args: &[String], replay: &replay::Replay, run: u32) {
+    if let Some(path) = flag_value(args, "--record") {
+        let path = recording_path(Path::new(path), run);
+        match replay.save(&path) {
+            Ok(()) => println!("Saved replay to {}", path.display()),
+            Err(err) => println!("Could not save replay: {}: {}", path.display(), err)
+        }
+    }
+}
+
+/// Uses the seed from the command line, then the level, then a random one.
+fn pick_seed(args: &[String], level: &level::Level) -> u32 {
+    let default = match level.seed {
+        None => world::random_seed(),
+        Some(x) => x
+    };
+    let seed = parse_flag(args, "--seed", default);
+    println!("Seed: {}", seed);
+    seed
+}
+
+/// Converts the legacy comma separated assets into a level file.
+///
+/// The files are read from `--legacy-dir <folder>`, or the assets folder.
+fn import_legacy(args: &[String], path: &Path) {
+    let dir = match flag_value(args, "--legacy-dir") {
+        None => root().join("./assets"),
+        Some(x) => PathBuf::from(x)
+    };
+    let level = match legacy::level(&dir) {
+        Ok(x) => x,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    match level.save(path) {
+        Ok(()) => println!("Saved level to {}", path.display()),
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes the sea current of a level to a CSV file and a PNG image.
+///
+/// `--resolution <pixels>` gives the distance between samples,
+/// `--time <seconds>` how far the arrow phases have advanced,
+/// `--seed <number>` the seed and
+/// `--level <file>` the level instead of the current one in the campaign.
+fn export_field(args: &[String], path: &Path) {
+    let resolution = parse_positive_flag(args, "--resolution",
+        settings::export::RESOLUTION);
+    let time = parse_finite_flag(args, "--time", 0.0);
+    let level = load_level(&load_campaign(args));
+    let seed = pick_seed(args, &level);
+    let size = settings::WINDOW_SIZE;
+    let rect = [0.0, 0.0, size[0] as f64, size[1] as f64];
+    let mut world = world::World::new(&level, load_config(args, &level), rect, seed);
+    let phase_vel = world.config.stream.phase_vel;
+    world.stream.update(time, phase_vel, 0.0);
+    match export::export(&world.stream, resolution, world.config.stream.speedup, path) {
+        Ok(()) => println!("Saved {} and {}",
+            path.with_extension("csv").display(),
+            path.with_extension("png").display()),
+        Err(err) => {
+            println!("Could not export the current: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Plays the level without a window and prints the final game state.
+///
+/// `--script <file>` gives the input sequence,
+/// `--dt <seconds>` the fixed time step,
+/// `--duration <seconds>` when to give up,
+/// `--seed <number>` the seed for random decisions and
+/// `--level <file>` the level to play instead of the campaign.
+/// With `--replay <file>` the recorded inputs are played instead.
+fn run_headless(args: &[String]) {
+    let script = match flag_value(args, "--script") {
+        None => headless::Script { steps: Vec::new() },
+        Some(path) => match headless::Script::from_path(Path::new(path)) {
+            Ok(x) => x,
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+        }
+    };
+    let dt = parse_positive_flag(args, "--dt", settings::headless::DT);
+    let duration = parse_flag(args, "--duration", settings::headless::DURATION);
+    let size = settings::WINDOW_SIZE;
+    let rect = [0.0, 0.0, size[0] as f64, size[1] as f64];
+    let game_state = match load_replay(args) {
+        Some(replay) => {
+            let level = load_level(&campaign::Campaign::single(&replay.level));
+            let config = replay_config(args, &level, &replay);
+            let mut world = world::World::new(&level, config, rect, replay.seed);
+            headless::play(&mut world, &replay)
+        }
+        None => {
+            let level = load_level(&load_campaign(args));
+            let seed = pick_seed(args, &level);
+            let mut world = world::World::new(&level, load_config(args, &level), rect, seed);
+            headless::run(&mut world, &script, dt, duration)
+        }
+    };
+    println!("{:?}", game_state);
+}
+
 /*
 fn init_audio() {
     sdl2::init(sdl2::INIT_AUDIO | sdl2::INIT_TIMER);
@@ -95,7 +431,7 @@ pub fn root() -> PathBuf {
     PathBuf::from("./")
 }
 
-fn load_assets(f: &mut FnMut()) {
+fn load_assets(f: &mut FnMut(&render::Textures)) {
     use opengl_graphics::Texture;
 
     // init_audio();
@@ -119,87 +455,81 @@ fn load_assets(f: &mut FnMut()) {
  
     // let mut win_music = WinMusic(mix::Music::from_file(&win_music).unwrap());
     // let mut lose_music = LoseMusic(mix::Music::from_file(&lose_music).unwrap());
-    let mut blood_text = render::BloodText(Texture::from_path(&blood).unwrap());
-    let mut you_win_text = render::YouWinText(Texture::from_path(&you_win).unwrap());
-    let mut you_lose_text = render::YouLoseText(Texture::from_path(&you_lose).unwrap());
-    let mut palm_tree = render::PalmTree(Texture::from_path(&palm_tree).unwrap());
-    let mut sea_bird = render::SeaBird(Texture::from_path(&sea_bird).unwrap());
-    let mut rock = render::Rock(Texture::from_path(&rock).unwrap());
-    let mut character = render::Character(Texture::from_path(&character).unwrap());
-
-    let blood_text_guard = CurrentGuard::new(&mut blood_text);
-    let you_win_text_guard = CurrentGuard::new(&mut you_win_text);
-    let you_lose_text_guard = CurrentGuard::new(&mut you_lose_text);
-    let palm_tree_guard = CurrentGuard::new(&mut palm_tree);
-    let sea_bird_guard = CurrentGuard::new(&mut sea_bird);
-    let rock_guard = CurrentGuard::new(&mut rock);
-    let character_guard = CurrentGuard::new(&mut character);
-    // let win_music_guard = CurrentGuard::new(&mut win_music);
-    // let lose_music_guard = CurrentGuard::new(&mut lose_music);
+    let textures = render::Textures {
+        blood_text: Texture::from_path(&blood).unwrap(),
+        you_win_text: Texture::from_path(&you_win).unwrap(),
+        you_lose_text: Texture::from_path(&you_lose).unwrap(),
+        palm_tree: Texture::from_path(&palm_tree).unwrap(),
+        sea_bird: Texture::from_path(&sea_bird).unwrap(),
+        rock: Texture::from_path(&rock).unwrap(),
+        character: Texture::from_path(&character).unwrap(),
+    };
 
     // Restart level if not quiting.
     while !start_piston::should_close() {
         // Loop infinite times. 
         // background_music.play(-1).unwrap();
         
-        f();
-    }
-
-    drop(blood_text_guard);
-    drop(you_win_text_guard);
-    drop(you_lose_text_guard);
-    drop(palm_tree_guard);
-    drop(sea_bird_guard);
-    drop(rock_guard);
-    drop(character_guard);
-    // drop(win_music_guard);
-    // drop(lose_music_guard);
-}
-
-/// Initialize current objects used as application structure
-fn setup() {
-    let mut stream = stream::Stream {
-        arrows: Vec::new(),
-        rect: sea_rect(),
-        strength: settings::stream::STRENGTH,
-        arrow_phases: Vec::new(),
+        f(&textures);
+    }
+}
+
+/// Creates a fresh world and plays it until restart.
+///
+/// Winning moves on to the next level, losing retries the same one.
+///
+/// With a replay the recorded inputs drive the player instead of the keyboard.
+fn setup(
+    textures: &render::Textures,
+    args: &[String],
+    bindings: &bindings::Bindings,
+    campaign: &mut campaign::Campaign,
+    playback: Option<&replay::Replay>,
+    run: u32
+) {
+    let level = load_level(campaign);
+    let seed = match playback {
+        Some(replay) => replay.seed,
+        None => pick_seed(args, &level)
     };
-    let mut moving_arrows: Vec<stream::MovingArrow> = Vec::new();    
-    let mut player = player::Player::new(settings::player::START_POS);
-    let mut rocks = rocks::Rocks { rocks: Vec::new() };
-    let mut selected_arrow = stream::SelectedArrow(None);
-    let mut game_state = game::GameState::Play;
-    let mut blood_bar = blood_bar::BloodBar(settings::blood_bar::START_VAL);
-    let mut blood = blood::Blood {
-        blood_drops: Vec::new(),
-        time_since_last_drop: 0.0,
+    let config = match playback {
+        Some(replay) => replay_config(args, &level, replay),
+        None => load_config(args, &level)
     };
-    let mut palm_trees = palm_trees::PalmTrees { palms: Vec::new() };
-    let mut sea_birds = sea_birds::SeaBirds::new();
-
-    let stream_guard = CurrentGuard::new(&mut stream);
-    let moving_arrows = CurrentGuard::new(&mut moving_arrows);
-    let player_guard = CurrentGuard::new(&mut player);
-    let rocks_guard = CurrentGuard::new(&mut rocks);
-    let selected_arrow_guard = CurrentGuard::new(&mut selected_arrow);
-    let game_state_guard = CurrentGuard::new(&mut game_state);
-    let blood_bar_guard = CurrentGuard::new(&mut blood_bar);
-    let blood_guard = CurrentGuard::new(&mut blood);
-    let palm_trees_guard = CurrentGuard::new(&mut palm_trees);
-    let sea_birds_guard = CurrentGuard::new(&mut sea_birds);
-
-    start();
-    
-    drop(stream_guard);
-    drop(moving_arrows);
-    drop(player_guard);
-    drop(rocks_guard);
-    drop(selected_arrow_guard);
-    drop(game_state_guard);
-    drop(blood_bar_guard);
-    drop(blood_guard);
-    drop(palm_trees_guard);
-    drop(sea_birds_guard);
+    let mut world = world::World::new(&level, config, sea_rect(), seed);
+    // Only the first run starts from the snapshot,
+    // later ones play the next level or retry this one from the start.
+    let snapshot_path = if run == 1 { flag_value(args, "--snapshot") } else { None };
+    if let Some(path) = snapshot_path {
+        match snapshot::Snapshot::from_path(Path::new(path)) {
+            Ok(x) => x.restore(&mut world),
+            Err(err) => println!("Could not load snapshot: {}", err)
+        }
+    }
+    let level_path = campaign.levels[campaign.current].clone();
+    let mut recording = if playback.is_none() && has_flag(args, "--record") {
+            Some(replay::Replay::new(&level_path, seed, &world.config))
+        } else {
+            None
+        };
+    let mut playback = playback.map(|x| replay::Playback::new(x.clone()));
+    // Reloaded values are not recorded, so they would not replay.
+    let watch = if has_flag(args, "--watch-config") && recording.is_none() {
+            Some(config::ConfigWatch::new(&config_path(args), level.overrides()))
+        } else {
+            if has_flag(args, "--watch-config") {
+                println!("The config file is not watched while recording");
+            }
+            None
+        };
+    start(&mut world, textures, bindings, &level_path, &config_path(args),
+        recording.as_mut(), playback.as_mut(), watch);
+    if let Some(ref recording) = recording {
+        save_recording(args, recording, run);
+    }
+    if world.game_state == game::GameState::Win {
+        campaign.win();
+    }
 }
 
 fn sea_rect() -> [f64; 4] {
@@ -210,34 +540,108 @@ fn sea_rect() -> [f64; 4] {
     [0.0, 0.0, size.width as f64, size.height as f64]
 }
 
-pub unsafe fn current_stream() -> Current<stream::Stream> { Current::new() }
-pub unsafe fn current_moving_arrows() -> Current<Vec<stream::MovingArrow>> { Current::new() }
-pub unsafe fn current_player() -> Current<player::Player> { Current::new() }
-pub unsafe fn current_rocks() -> Current<rocks::Rocks> { Current::new() }
-pub unsafe fn current_selected_arrow() -> Current<stream::SelectedArrow> { Current::new() }
-pub unsafe fn current_game_state() -> Current<game::GameState> { Current::new() }
-pub unsafe fn current_blood_text() -> Current<render::BloodText> { Current::new() }
-pub unsafe fn current_blood_bar() -> Current<blood_bar::BloodBar> { Current::new() }
-pub unsafe fn current_you_win_text() -> Current<render::YouWinText> { Current::new() }
-pub unsafe fn current_you_lose_text() -> Current<render::YouLoseText> { Current::new() }
-pub unsafe fn current_blood() -> Current<blood::Blood> { Current::new() }
-pub unsafe fn current_palm_tree() -> Current<render::PalmTree> { Current::new() }
-pub unsafe fn current_palm_trees() -> Current<palm_trees::PalmTrees> { Current::new() }
-pub unsafe fn current_sea_birds() -> Current<sea_birds::SeaBirds> { Current::new() }
-pub unsafe fn current_sea_bird() -> Current<render::SeaBird> { Current::new() }
-pub unsafe fn current_rock() -> Current<render::Rock> { Current::new() }
-pub unsafe fn current_character() -> Current<render::Character> { Current::new() }
-// pub unsafe fn current_win_music() -> Current<WinMusic> { Current::new() }
-// pub unsafe fn current_lose_music() -> Current<LoseMusic> { Current::new() }
-
-fn start() {
-    settings::stream::load();
-    stream::refresh_moving_arrows();
-    settings::rocks::load();
-    settings::palm_trees::load();
-    settings::sea_birds::load();
+/// Writes a snapshot of the world to the quick save file.
+fn quick_save(world: &world::World) {
+    let path = root().join(settings::utils::QUICK_SAVE_FILE);
+    match snapshot::Snapshot::take(world).save(&path) {
+        Ok(()) => println!("Saved snapshot to {}", path.display()),
+        Err(err) => println!("Could not save snapshot: {}", err)
+    }
+}
+
+/// Restores the world from the quick save file,
+/// keeping the movement keys that are held down.
+fn quick_load(world: &mut world::World) {
+    let path = root().join(settings::utils::QUICK_SAVE_FILE);
+    match snapshot::Snapshot::from_path(&path) {
+        Ok(x) => {
+            let key_state = world.player.key_state;
+            x.restore(world);
+            world.player.key_state = key_state;
+        }
+        Err(err) => println!("Could not load snapshot: {}", err)
+    }
+}
+
+/// Writes the edited level over its file, keeping a backup of the old one.
+fn save_level(world: &world::World, path: &Path) {
+    let saved = level::Level::from_path(path)
+        .and_then(|base| editor::level(world, &base).replace(path));
+    match saved {
+        Ok(()) => println!("Saved level to {}", path.display()),
+        Err(err) => println!("Could not save level: {}", err)
+    }
+}
+
+/// Reads the level file again and continues editing it.
+///
+/// The tuning values are read again from the config file,
+/// with the overrides of the reloaded level on top.
+fn reload_level(
+    world: &mut world::World,
+    path: &Path,
+    config_path: &Path,
+    watch: &mut Option<config::ConfigWatch>
+) {
+    let level = match level::Level::from_path(path) {
+            Ok(x) => x,
+            Err(err) => {
+                println!("Could not reload level: {}", err);
+                return;
+            }
+        };
+    let config = config::Config::from_path(config_path)
+        .and_then(|config| config.with_overrides(&level.overrides()));
+    let config = match config {
+            Ok(x) => x,
+            Err(err) => {
+                println!("Could not reload level: {}", err);
+                return;
+            }
+        };
+    if let Some(ref mut watch) = *watch {
+        watch.overrides = level.overrides();
+    }
+    let rect = world.stream.rect;
+    let seed = world.seed;
+    let tool = world.editor.tool;
+    *world = world::World::new(&level, config, rect, seed);
+    world.editing = true;
+    world.editor.tool = tool;
+    println!("Reloaded level from {}", path.display());
+}
+
+/// Applies an input to the world, and records it when recording.
+fn input(
+    recording: &mut Option<&mut replay::Replay>,
+    world: &mut world::World,
+    event: replay::ReplayEvent
+) {
+    match *recording {
+        Some(ref mut recording) => recording.record(world, event),
+        None => event.apply(world)
+    }
+}
+
+/// Plays until restart.
+///
+/// While recording, inputs that the replay cannot reproduce are refused.
+fn start(
+    world: &mut world::World,
+    textures: &render::Textures,
+    bindings: &bindings::Bindings,
+    level_path: &Path,
+    config_path: &Path,
+    mut recording: Option<&mut replay::Replay>,
+    mut playback: Option<&mut replay::Playback>,
+    mut watch: Option<config::ConfigWatch>
+) {
+    use bindings::Action;
+    use replay::ReplayEvent;
 
     let mut cursor: [f64; 2] = [0.0; 2];
+    let mut held = bindings::HeldButtons::new();
+    let mut restart = false;
     for e in start_piston::events() {
         use piston::event::{ 
             MouseCursorEvent, PressEvent, 
@@ -247,7 +651,7 @@ fn start() {
         e.render(|_args| {
             start_piston::render_2d_opengl(
                 Some(settings::background_color()), |c, g| {
-                render::render(&c, g);
+                render::render(world, textures, &c, g);
             });
 
             start_piston::set_title(
@@ -255,97 +659,144 @@ fn start() {
             );
         });
         e.update(|args| {
-            let dt = args.dt;
-            if game::should_update() {
-                stream::update_stream(dt);
-                stream::update_moving_arrows(dt);
-                player::update_player(dt);
-                blood_bar::update_blood_bar(dt);
-                blood::update_blood(dt);
+            if let Some(ref mut watch) = watch {
+                if let Some(config) = watch.update(args.dt) {
+                    println!("Reloaded {}", watch.path.display());
+                    world.config = config;
+                }
+            }
+            // Edits are not recorded, so neither is the time spent on them.
+            if world.editing { return; }
+            match playback {
+                Some(ref mut playback) => { playback.next_frame(world); }
+                None => input(&mut recording, world, ReplayEvent::Update(args.dt))
             }
-
-            game::update_game_state();
         });
-        if game::should_update() {
-            sea_birds::update_sea_birds(&e);
-        }
 
         e.mouse_cursor(|x, y| {
             cursor = [x, y];
-            if settings::EDIT {
-                stream::edit_selected_arrow(cursor);
+            if world.editing {
+                stream::edit_selected_arrow(world, cursor);
+                primitives::edit_selected_primitive(world, cursor);
+                editor::drag(world, cursor);
             }
         });
         e.press(|button| {
-            if settings::EDIT {
-                if button == settings::stream::ADD_ARROW_BUTTON {
-                    stream::add_arrow(cursor);
-                    stream::refresh_moving_arrows();
-                }
-                if button == settings::utils::PRINT_CURSOR_POS {
-                    println!("{}, {},", cursor[0], cursor[1]);
-                }
-                if button == settings::utils::PRINT_PLAYER_POS {
-                    let pos = unsafe { current_player() }.pos;
-                    println!("{}, {},", pos[0], pos[1]);
-                }
-                if button == settings::utils::PRINT_STREAM {
-                    println!("Stream:");
-                    for (arrow, phase) in unsafe { current_stream() }.arrows.iter().zip(
-                        unsafe { current_stream() }.arrow_phases.iter()) {
-                        println!("{}, {}, {}, {}, {},", arrow.pos[0], arrow.pos[1],
-                            arrow.dir[0], arrow.dir[1], *phase);
+            let actions = bindings.actions(button);
+            // Movements that are already going on through another button.
+            let moving: Vec<bool> = actions.iter()
+                .map(|&action| held.is_held(bindings, action))
+                .collect();
+            held.press(button);
+            for (&action, &moving) in actions.iter().zip(moving.iter()) {
+                if let Some(key) = bindings::movement_key(action) {
+                    if playback.is_none() && !moving {
+                        input(&mut recording, world, ReplayEvent::Press(key));
                     }
+                    continue;
                 }
-                if button == settings::utils::PRINT_HAS_WON {
-                    println!("{}", game::won());
+                match action {
+                    Action::Restart => {
+                        restart = match world.game_state {
+                                game::GameState::Win
+                              | game::GameState::Lose => true,
+                                _ => false
+                            };
+                    }
+                    Action::QuickSave if playback.is_none() => quick_save(world),
+                    Action::QuickLoad if recording.is_some() => {
+                        println!("Quick load is not available while recording");
+                    }
+                    Action::QuickLoad if playback.is_none() => quick_load(world),
+                    Action::ToggleEditor if recording.is_some() => {
+                        println!("The editor is not available while recording");
+                    }
+                    Action::ToggleEditor if playback.is_none() => editor::toggle(world),
+                    _ if !world.editing => {}
+                    Action::ArrowTool | Action::RockTool
+                  | Action::PalmTreeTool | Action::SeaBirdTool => {
+                        if let Some(tool) = bindings::tool(action) {
+                            editor::select_tool(world, tool);
+                        }
+                    }
+                    Action::Grab => editor::grab(world, cursor),
+                    Action::Delete => editor::delete(world, cursor),
+                    Action::Undo => history::undo(world),
+                    Action::Redo => history::redo(world),
+                    Action::SaveLevel => save_level(world, level_path),
+                    Action::ReloadLevel =>
+                        reload_level(world, level_path, config_path, &mut watch),
+                    Action::AddWhirlpool | Action::AddOutflow | Action::AddJet => {
+                        if let Some(kind) = bindings::primitive_kind(action) {
+                            editor::add_primitive(world, kind, cursor);
+                        }
+                    }
+                    Action::AddArrow => editor::add_arrow(world, cursor),
+                    Action::CycleWaveform => {
+                        editor::change_arrow(world, cursor, stream::cycle_waveform);
+                    }
+                    Action::FasterOscillation => {
+                        let step = settings::stream::FREQUENCY_STEP;
+                        editor::change_arrow(world, cursor,
+                            |w, pos| stream::scale_frequency(w, pos, step));
+                    }
+                    Action::SlowerOscillation => {
+                        let step = settings::stream::FREQUENCY_STEP;
+                        editor::change_arrow(world, cursor,
+                            |w, pos| stream::scale_frequency(w, pos, 1.0 / step));
+                    }
+                    Action::PhaseForward => {
+                        let step = settings::stream::PHASE_STEP;
+                        editor::change_arrow(world, cursor,
+                            |w, pos| stream::shift_phase(w, pos, step));
+                    }
+                    Action::PhaseBack => {
+                        let step = settings::stream::PHASE_STEP;
+                        editor::change_arrow(world, cursor,
+                            |w, pos| stream::shift_phase(w, pos, -step));
+                    }
+                    Action::PrintCursorPos => {
+                        println!("{}, {},", cursor[0], cursor[1]);
+                    }
+                    Action::PrintPlayerPos => {
+                        let pos = world.player.pos;
+                        println!("{}, {},", pos[0], pos[1]);
+                    }
+                    Action::PrintStream => {
+                        println!("Stream:");
+                        for (arrow, phase) in world.stream.arrows.iter().zip(
+                            world.stream.arrow_phases.iter()) {
+                            println!("{}, {}, {}, {}, {},", arrow.pos[0], arrow.pos[1],
+                                arrow.dir[0], arrow.dir[1], *phase);
+                        }
+                        println!("Primitives:");
+                        println!("{}", rustc_serialize::json::as_pretty_json(
+                            &world.stream.primitives));
+                    }
+                    Action::PrintHasWon => {
+                        println!("{}", game::won(world));
+                    }
+                    _ => {}
                 }
             }
-            if button == settings::player::MOVE_LEFT_BUTTON {
-                unsafe { current_player() }.key_state.insert(player::LEFT);
-            }
-            if button == settings::player::MOVE_RIGHT_BUTTON {
-                unsafe { current_player() }.key_state.insert(player::RIGHT);
-            }
-            if button == settings::player::MOVE_UP_BUTTON {
-                unsafe { current_player() }.key_state.insert(player::UP);
-            }
-            if button == settings::player::MOVE_DOWN_BUTTON {
-                unsafe { current_player() }.key_state.insert(player::DOWN);
-            }
         });
         e.release(|button| {
-            if button == settings::stream::ADD_ARROW_BUTTON {
-                stream::deselect_arrow();
-            }
-            if button == settings::player::MOVE_LEFT_BUTTON {
-                unsafe { current_player() }.key_state.remove(player::LEFT);
-            }
-            if button == settings::player::MOVE_RIGHT_BUTTON {
-                unsafe { current_player() }.key_state.remove(player::RIGHT);
-            }
-            if button == settings::player::MOVE_UP_BUTTON {
-                unsafe { current_player() }.key_state.remove(player::UP);
-            }
-            if button == settings::player::MOVE_DOWN_BUTTON {
-                unsafe { current_player() }.key_state.remove(player::DOWN);
+            held.release(button);
+            for action in bindings.actions(button) {
+                if let Some(key) = bindings::movement_key(action) {
+                    if playback.is_none() && !held.is_held(bindings, action) {
+                        input(&mut recording, world, ReplayEvent::Release(key));
+                    }
+                }
+                if !world.editing { continue; }
+                if action == Action::AddArrow || action == Action::Grab
+                || bindings::primitive_kind(action).is_some() {
+                    editor::release(world);
+                }
             }
         });
 
-        let restart = e.press(|button| {
-            let game_state = unsafe { *current_game_state() };
-            let can_restart = match game_state {
-                    game::GameState::Win
-                  | game::GameState::Lose => true,
-                    _ => false
-                };
-            if can_restart && button == settings::utils::RESTART_LEVEL {
-                true
-            } else {
-                false
-            }
-        });
-        if restart == Some(true) { break; } 
+        if restart { break; }
     }
 }
 
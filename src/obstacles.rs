@@ -0,0 +1,279 @@
+//! Bends the sea current around rocks
+//!
+//! Each rock is a cylinder in potential flow.
+//! For a current `u` and the direction `n` from the rock center,
+//! the flow `u - (radius / r)^2 * (2 * (u · n) * n - u)`
+//! has no component into the rock at its surface and becomes `u` far away.
+//! Behind each rock, two small whirlpools turning against each other
+//! make the water circle back into the shadow of the rock.
+//! Where several rocks are close, the closest one bends the current last,
+//! which keeps its surface free of inflow even with the others and the wakes around.
+
+use primitives::Whirlpool;
+
+#[derive(Clone)]
+pub struct Obstacles {
+    pub centers: Vec<[f64; 2]>,
+    pub radius: f64,
+    /// The current at each center before it is bent,
+    /// which decides where the wake goes.
+    pub free_streams: Vec<[f64; 2]>,
+    /// The rocks sorted into cells, rebuilt by `refresh`.
+    grid: Grid,
+}
+
+/// Rocks sorted into square cells as wide as their influence,
+/// so a position only needs to visit its own cell and the ones around it.
+#[derive(Clone)]
+struct Grid {
+    /// The corner of the first cell.
+    origin: [f64; 2],
+    cell_size: f64,
+    /// The number of columns and rows.
+    size: [usize; 2],
+    /// The rock indices of each cell, row by row.
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    fn new() -> Grid {
+        Grid {
+            origin: [0.0; 2],
+            cell_size: 1.0,
+            size: [0; 2],
+            cells: Vec::new(),
+        }
+    }
+
+    /// Covers the rock centers with cells of a size.
+    fn build(centers: &[[f64; 2]], cell_size: f64) -> Grid {
+        if centers.len() == 0 || !(cell_size > 0.0) { return Grid::new(); }
+
+        let mut min = centers[0];
+        let mut max = centers[0];
+        for c in centers.iter() {
+            min = [min[0].min(c[0]), min[1].min(c[1])];
+            max = [max[0].max(c[0]), max[1].max(c[1])];
+        }
+        let size = [
+            ((max[0] - min[0]) / cell_size) as usize + 1,
+            ((max[1] - min[1]) / cell_size) as usize + 1
+        ];
+        let mut cells = vec![Vec::new(); size[0] * size[1]];
+        for (i, c) in centers.iter().enumerate() {
+            let x = (((c[0] - min[0]) / cell_size) as usize).min(size[0] - 1);
+            let y = (((c[1] - min[1]) / cell_size) as usize).min(size[1] - 1);
+            cells[y * size[0] + x].push(i);
+        }
+        Grid {
+            origin: min,
+            cell_size: cell_size,
+            size: size,
+            cells: cells,
+        }
+    }
+
+    /// Calls `f` with every rock that can be closer than one cell size,
+    /// always in the same order.
+    fn near<F>(&self, pos: [f64; 2], mut f: F) where F: FnMut(usize) {
+        if self.cells.len() == 0 { return; }
+
+        let x = ((pos[0] - self.origin[0]) / self.cell_size).floor();
+        let y = ((pos[1] - self.origin[1]) / self.cell_size).floor();
+        let (x0, x1) = ((x - 1.0).max(0.0), (x + 1.0).min(self.size[0] as f64 - 1.0));
+        let (y0, y1) = ((y - 1.0).max(0.0), (y + 1.0).min(self.size[1] as f64 - 1.0));
+        if !(x0 <= x1 && y0 <= y1) { return; }
+        for j in y0 as usize..y1 as usize + 1 {
+            for i in x0 as usize..x1 as usize + 1 {
+                for &k in self.cells[j * self.size[0] + i].iter() {
+                    f(k);
+                }
+            }
+        }
+    }
+}
+
+impl Obstacles {
+    pub fn new() -> Obstacles {
+        Obstacles {
+            centers: Vec::new(),
+            radius: 0.0,
+            free_streams: Vec::new(),
+            grid: Grid::new(),
+        }
+    }
+
+    /// Samples the current at each center again,
+    /// and sorts the rocks into cells.
+    ///
+    /// Must be called when the centers or the radius change.
+    pub fn refresh<F>(&mut self, f: F) where F: Fn([f64; 2]) -> [f64; 2] {
+        use settings::rocks::INFLUENCE;
+
+        self.free_streams = self.centers.iter().map(|&pos| f(pos)).collect();
+        self.grid = Grid::build(&self.centers, self.radius * INFLUENCE);
+    }
+
+    /// Bends the current `u` at a position around the rocks.
+    ///
+    /// Each rock bends the current already bent by the others,
+    /// with its wake added first. The closest rock goes last,
+    /// so no water flows into it at its surface.
+    /// Between `INFLUENCE / 2` and `settings::rocks::INFLUENCE` radii
+    /// a rock fades out smoothly, and further away it is skipped.
+    /// There is no current inside a rock.
+    pub fn deflect(&self, pos: [f64; 2], u: [f64; 2]) -> [f64; 2] {
+        use vecmath::vec2_len as len;
+        use vecmath::vec2_sub as sub;
+        use settings::rocks::INFLUENCE;
+
+        let a = self.radius;
+        if a <= 0.0 { return u; }
+
+        let mut inside = false;
+        let mut closest: Option<(usize, f64)> = None;
+        self.grid.near(pos, |i| {
+            let r = len(sub(pos, self.centers[i]));
+            if r < a { inside = true; }
+            if r < a * INFLUENCE && closest.map_or(true, |(_, x)| r < x) {
+                closest = Some((i, r));
+            }
+        });
+        if inside { return [0.0; 2]; }
+        let (closest, closest_r) = match closest {
+                None => { return u; }
+                Some(x) => x
+            };
+
+        let mut res = u;
+        self.grid.near(pos, |i| {
+            if i == closest { return; }
+            let r = len(sub(pos, self.centers[i]));
+            if r < a * INFLUENCE { res = self.apply(i, pos, r, res); }
+        });
+        self.apply(closest, pos, closest_r, res)
+    }
+
+    /// Adds the wake of one rock and bends the current around it,
+    /// faded out by the distance `r`.
+    fn apply(&self, i: usize, pos: [f64; 2], r: f64, u: [f64; 2]) -> [f64; 2] {
+        let fade = fade(r / self.radius);
+        let wake = self.wake(i, pos);
+        let u = [u[0] + wake[0] * fade, u[1] + wake[1] * fade];
+        self.bend(i, pos, r, u, fade)
+    }
+
+    /// The cylinder flow around one rock,
+    /// with its correction scaled by `fade`.
+    fn bend(&self, i: usize, pos: [f64; 2], r: f64, u: [f64; 2], fade: f64) -> [f64; 2] {
+        use vecmath::vec2_dot as dot;
+
+        let a = self.radius;
+        let center = self.centers[i];
+        let n = [(pos[0] - center[0]) / r, (pos[1] - center[1]) / r];
+        let f = a * a / (r * r) * fade;
+        let un = 2.0 * dot(u, n);
+        [u[0] - f * (un * n[0] - u[0]), u[1] - f * (un * n[1] - u[1])]
+    }
+
+    /// Two small whirlpools behind a rock, turning against each other.
+    fn wake(&self, i: usize, pos: [f64; 2]) -> [f64; 2] {
+        use vecmath::vec2_len as len;
+        use settings::rocks::WAKE;
+
+        let a = self.radius;
+        let center = self.centers[i];
+        let free = self.free_streams[i];
+        let speed = len(free);
+        if speed == 0.0 { return [0.0; 2]; }
+
+        let dir = [free[0] / speed, free[1] / speed];
+        let side = [-dir[1], dir[0]];
+        let mut res = [0.0; 2];
+        for &(s, spin) in [(1.0, -1.0), (-1.0, 1.0)].iter() {
+            let whirlpool = Whirlpool {
+                pos: [
+                    center[0] + dir[0] * a * 1.6 + side[0] * a * 0.5 * s,
+                    center[1] + dir[1] * a * 1.6 + side[1] * a * 0.5 * s
+                ],
+                radius: a * 0.4,
+                spin: spin * WAKE * speed,
+            };
+            let w = whirlpool.at(pos);
+            res[0] += w[0];
+            res[1] += w[1];
+        }
+        res
+    }
+}
+
+/// One up to `INFLUENCE / 2` radii from a rock, then smoothly down to zero
+/// at `INFLUENCE` radii, so the current does not jump where a rock is skipped.
+fn fade(radii: f64) -> f64 {
+    use settings::rocks::INFLUENCE;
+
+    let start = INFLUENCE * 0.5;
+    let t = ((radii - start) / (INFLUENCE - start)).max(0.0).min(1.0);
+    1.0 - t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Obstacles;
+    use settings::rocks::INFLUENCE;
+
+    fn rocks(centers: Vec<[f64; 2]>) -> Obstacles {
+        let mut rocks = Obstacles::new();
+        rocks.radius = 20.0;
+        rocks.centers = centers;
+        rocks.refresh(|_| [30.0, 10.0]);
+        rocks
+    }
+
+    #[test]
+    fn no_inflow_with_neighbors() {
+        let rocks = rocks(vec![[0.0, 0.0], [50.0, 0.0], [20.0, 45.0]]);
+        let a = rocks.radius;
+        for &center in rocks.centers.iter() {
+            for i in 0..64 {
+                let angle = i as f64 / 64.0 * 2.0 * ::std::f64::consts::PI;
+                let n = [angle.cos(), angle.sin()];
+                let pos = [center[0] + n[0] * a * 1.000001, center[1] + n[1] * a * 1.000001];
+                if rocks.centers.iter().any(|c| c != &center &&
+                    (pos[0] - c[0]).hypot(pos[1] - c[1]) < a) { continue; }
+                let v = rocks.deflect(pos, [30.0, 10.0]);
+                let un = v[0] * n[0] + v[1] * n[1];
+                assert!(un.abs() < 1e-3, "inflow {} at {:?}", un, pos);
+            }
+        }
+    }
+
+    #[test]
+    fn far_rocks_skipped() {
+        let one = rocks(vec![[0.0, 0.0]]);
+        let many = rocks(vec![[-500.0, 300.0], [0.0, 0.0], [1000.0, -40.0], [300.0, 0.0]]);
+        for i in 0..100 {
+            let pos = [i as f64 * 1.1 - 30.0, 25.0];
+            assert_eq!(one.deflect(pos, [30.0, 10.0]), many.deflect(pos, [30.0, 10.0]));
+        }
+    }
+
+    #[test]
+    fn fades_out() {
+        let rocks = rocks(vec![[0.0, 0.0]]);
+        let a = rocks.radius;
+        let u = [30.0, 10.0];
+        for &dir in [[1.0, 0.0], [0.0, 1.0], [-0.6, 0.8]].iter() {
+            let mut last = rocks.deflect([dir[0] * a * 2.0, dir[1] * a * 2.0], u);
+            let steps = 4000;
+            for i in 1..steps + 1 {
+                let r = a * (2.0 + (INFLUENCE - 1.0) * i as f64 / steps as f64);
+                let v = rocks.deflect([dir[0] * r, dir[1] * r], u);
+                let jump = (v[0] - last[0]).hypot(v[1] - last[1]);
+                assert!(jump < 0.05, "jump {} at {} radii", jump, r / a);
+                last = v;
+            }
+            assert_eq!(last, u);
+        }
+    }
+}
@@ -1,6 +1,10 @@
 
 #![allow(dead_code)]
 
+use rustc_serialize::{ Decodable, Decoder, Encodable, Encoder };
+use integrator;
+use world::World;
+
 bitflags! {
     flags KeyState: u8 {
         const LEFT = 0b1,
@@ -10,6 +14,20 @@ bitflags! {
     }
 }
 
+// Stored as bits, since bitflags does not derive serialization.
+impl Encodable for KeyState {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        self.bits().encode(s)
+    }
+}
+
+impl Decodable for KeyState {
+    fn decode<D: Decoder>(d: &mut D) -> Result<KeyState, D::Error> {
+        let bits: u8 = try!(Decodable::decode(d));
+        Ok(KeyState::from_bits_truncate(bits))
+    }
+}
+
 impl KeyState {
     pub fn acceleration(&self, d: f64) -> [f64; 2] {
         let mut acc = [0.0; 2];
@@ -29,11 +47,32 @@ impl KeyState {
     }
 }
 
+/// Reads a movement key from its name.
+pub fn parse_key(name: &str) -> Option<KeyState> {
+    match name {
+        "left" => Some(LEFT),
+        "right" => Some(RIGHT),
+        "up" => Some(UP),
+        "down" => Some(DOWN),
+        _ => None
+    }
+}
+
+/// The name of a single movement key.
+pub fn key_name(key: KeyState) -> &'static str {
+    if key == LEFT { "left" }
+    else if key == RIGHT { "right" }
+    else if key == UP { "up" }
+    else { "down" }
+}
+
+#[derive(Clone, RustcEncodable, RustcDecodable)]
 pub enum State {
     Bitten(f64),
     Normal,
 }
 
+#[derive(Clone, RustcEncodable, RustcDecodable)]
 pub struct Player {
     pub pos: [f64; 2],
     pub vel: [f64; 2],
@@ -56,24 +95,17 @@ impl Player {
     }
 }
 
-pub fn update_player(dt: f64) {
-    use current_stream;
-    use current_player;
-    use current_rocks;
+pub fn update_player(w: &mut World, dt: f64) {
     use vecmath::vec2_add as add;
     use vecmath::vec2_scale as scale;
     use vecmath::vec2_sub as sub;
     use vecmath::vec2_len as len;
     use vecmath::vec2_square_len as square_len;
-    use settings::WATER_FRICTION;
-    use settings::player::{ ACC, FRAME_INTERVAL, FRAMES, SPEEDUP };
-
-    let dt = dt * SPEEDUP;
+    use settings::player::{ FRAME_INTERVAL, FRAMES };
 
-    let stream = unsafe { &mut *current_stream() };
-    let player = unsafe { &mut *current_player() };
-    let rocks = unsafe { &mut *current_rocks() };    
-    let friction = WATER_FRICTION;
+    let World { ref stream, ref mut player, ref rocks, ref config, .. } = *w;
+    let dt = dt * config.player.speedup;
+    let friction = config.water_friction;
 
     player.time_since_last_frame_update += dt;
     if player.time_since_last_frame_update > FRAME_INTERVAL {
@@ -96,7 +128,7 @@ pub fn update_player(dt: f64) {
             }
         };
 
-    let acc = player.key_state.acceleration(ACC);
+    let acc = player.key_state.acceleration(config.player.acc);
 
     let drag = (-square_len(player.vel) * friction).exp();
     let vel = scale(player.vel, drag);
@@ -107,11 +139,12 @@ pub fn update_player(dt: f64) {
 
     player.vel = scale(add(vel, next_vel), 0.5);
 
-    let dir = stream.at(player.pos);
-    let next_pos = add(player.pos, add(scale(dir, dt), scale(player.vel, dt)));
+    let vel = player.vel;
+    let next_pos = integrator::advect(config.integrator, player.pos, dt,
+        |pos| add(stream.at(pos), vel));
 
     let mut hits_rock = false;
-    let rock_radius = ::settings::rocks::RADIUS;
+    let rock_radius = config.rocks.radius;
     for rock in rocks.rocks.iter() {
         let diff = sub(rock.pos, next_pos);
         if len(diff) < rock_radius {
@@ -0,0 +1,182 @@
+//! Shapes of current that are added on top of the arrows
+
+use world::World;
+
+/// Swirls around a center.
+#[derive(Copy, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Whirlpool {
+    pub pos: [f64; 2],
+    /// The distance where the water moves fastest.
+    pub radius: f64,
+    /// The fastest speed, positive turns clockwise on the screen.
+    pub spin: f64,
+}
+
+/// Pushes water away from a center, or pulls it in when negative.
+#[derive(Copy, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Outflow {
+    pub pos: [f64; 2],
+    /// The distance where the water moves fastest.
+    pub radius: f64,
+    /// The fastest speed.
+    pub strength: f64,
+}
+
+/// Flows along a line from start to end.
+#[derive(Copy, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Jet {
+    pub start: [f64; 2],
+    pub end: [f64; 2],
+    /// How far from the line the jet reaches.
+    pub width: f64,
+    /// The speed along the line.
+    pub strength: f64,
+}
+
+/// Falls off smoothly on both sides of the radius, where it is 1.
+fn ring(r: f64, radius: f64) -> f64 {
+    2.0 * r * radius / (r * r + radius * radius)
+}
+
+impl Whirlpool {
+    pub fn at(&self, pos: [f64; 2]) -> [f64; 2] {
+        let (dx, dy) = (pos[0] - self.pos[0], pos[1] - self.pos[1]);
+        let r = (dx * dx + dy * dy).sqrt();
+        if r == 0.0 { return [0.0; 2]; }
+        let speed = self.spin * ring(r, self.radius) / r;
+        [-dy * speed, dx * speed]
+    }
+}
+
+impl Outflow {
+    pub fn at(&self, pos: [f64; 2]) -> [f64; 2] {
+        let (dx, dy) = (pos[0] - self.pos[0], pos[1] - self.pos[1]);
+        let r = (dx * dx + dy * dy).sqrt();
+        if r == 0.0 { return [0.0; 2]; }
+        let speed = self.strength * ring(r, self.radius) / r;
+        [dx * speed, dy * speed]
+    }
+}
+
+impl Jet {
+    pub fn at(&self, pos: [f64; 2]) -> [f64; 2] {
+        use vecmath::vec2_sub as sub;
+        use vecmath::vec2_dot as dot;
+        use vecmath::vec2_len as len;
+        use vecmath::vec2_scale as scale;
+        use vecmath::vec2_add as add;
+
+        let line = sub(self.end, self.start);
+        let l = len(line);
+        if l == 0.0 { return [0.0; 2]; }
+        let dir = scale(line, 1.0 / l);
+        let t = dot(sub(pos, self.start), dir).max(0.0).min(l);
+        let d = len(sub(pos, add(self.start, scale(dir, t)))) / self.width.max(0.01);
+        scale(dir, self.strength * (-d * d).exp())
+    }
+}
+
+/// All the shapes of current in a stream.
+#[derive(Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Primitives {
+    pub whirlpools: Vec<Whirlpool>,
+    pub outflows: Vec<Outflow>,
+    pub jets: Vec<Jet>,
+}
+
+impl Primitives {
+    pub fn new() -> Primitives {
+        Primitives {
+            whirlpools: Vec::new(),
+            outflows: Vec::new(),
+            jets: Vec::new(),
+        }
+    }
+
+    /// Sums up the current of every shape.
+    pub fn at(&self, pos: [f64; 2]) -> [f64; 2] {
+        use vecmath::vec2_add as add;
+
+        let mut sum = [0.0; 2];
+        for x in self.whirlpools.iter() { sum = add(sum, x.at(pos)); }
+        for x in self.outflows.iter() { sum = add(sum, x.at(pos)); }
+        for x in self.jets.iter() { sum = add(sum, x.at(pos)); }
+        sum
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PrimitiveKind {
+    Whirlpool,
+    Outflow,
+    Jet,
+}
+
+/// The shape being placed in edit mode.
+#[derive(Copy, Clone)]
+pub struct SelectedPrimitive(pub Option<(PrimitiveKind, usize)>);
+
+/// Adds a shape with the default size at a position and selects it.
+pub fn add_primitive(w: &mut World, kind: PrimitiveKind, pos: [f64; 2]) {
+    use settings::primitives::*;
+
+    let mut primitives = w.stream.primitives.clone();
+    let id = match kind {
+        PrimitiveKind::Whirlpool => {
+            primitives.whirlpools.push(Whirlpool {
+                pos: pos,
+                radius: RADIUS,
+                spin: WHIRLPOOL_SPIN,
+            });
+            primitives.whirlpools.len() - 1
+        }
+        PrimitiveKind::Outflow => {
+            primitives.outflows.push(Outflow {
+                pos: pos,
+                radius: RADIUS,
+                strength: OUTFLOW_STRENGTH,
+            });
+            primitives.outflows.len() - 1
+        }
+        PrimitiveKind::Jet => {
+            primitives.jets.push(Jet {
+                start: pos,
+                end: pos,
+                width: JET_WIDTH,
+                strength: JET_STRENGTH,
+            });
+            primitives.jets.len() - 1
+        }
+    };
+    w.stream.set_primitives(primitives);
+    w.selected_primitive = SelectedPrimitive(Some((kind, id)));
+}
+
+/// Drags the size of a whirlpool or outflow, or the end of a jet.
+pub fn edit_selected_primitive(w: &mut World, pos: [f64; 2]) {
+    use vecmath::vec2_sub as sub;
+    use vecmath::vec2_len as len;
+
+    let SelectedPrimitive(selected) = w.selected_primitive;
+    let (kind, id) = match selected {
+        None => { return; }
+        Some(x) => x
+    };
+    let mut primitives = w.stream.primitives.clone();
+    match kind {
+        PrimitiveKind::Whirlpool => {
+            let x = &mut primitives.whirlpools[id];
+            x.radius = len(sub(pos, x.pos)).max(1.0);
+        }
+        PrimitiveKind::Outflow => {
+            let x = &mut primitives.outflows[id];
+            x.radius = len(sub(pos, x.pos)).max(1.0);
+        }
+        PrimitiveKind::Jet => primitives.jets[id].end = pos,
+    }
+    w.stream.set_primitives(primitives);
+}
+
+pub fn deselect_primitive(w: &mut World) {
+    w.selected_primitive = SelectedPrimitive(None);
+}
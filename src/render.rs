@@ -1,94 +1,179 @@
-use graphics::Context;
+use graphics::{ Context, Line };
 use opengl_graphics::{ Texture, GlGraphics };
+use world::World;
+
+/// The images used to draw the game.
+pub struct Textures {
+    pub blood_text: Texture,
+    pub you_win_text: Texture,
+    pub you_lose_text: Texture,
+    pub palm_tree: Texture,
+    pub sea_bird: Texture,
+    pub rock: Texture,
+    pub character: Texture,
+}
+
+pub fn render(w: &World, t: &Textures, c: &Context, g: &mut GlGraphics) {
+    if w.editing {
+        stream_arrows(w, c, g);
+        primitives(w, c, g);
+    }
+    moving_arrows(w, c, g);
+    blood(w, c, g);
+    beach(w, c, g);
+    rocks(w, t, c, g);
+    palm_tree(w, t, c, g);
+    player(w, t, c, g);
+    sea_birds(w, t, c, g);
+    blood_bar(w, t, c, g);
+    you_win(w, t, c, g);
+    you_lose(w, t, c, g);
+    if w.editing {
+        editor_objects(w, c, g);
+        editor_frame(c, g);
+    }
+}
+
+/// Shows that the game is paused for editing.
+pub fn editor_frame(c: &Context, g: &mut GlGraphics) {
+    use graphics::Rectangle;
+    use settings::WINDOW_SIZE;
+    use settings::editor::{ FRAME_COLOR, FRAME_WIDTH };
+
+    let (w, h) = (WINDOW_SIZE[0] as f64, WINDOW_SIZE[1] as f64);
+    let d = FRAME_WIDTH;
+    let rect = Rectangle::new(FRAME_COLOR);
+    for &r in [
+        [0.0, 0.0, w, d],
+        [0.0, h - d, w, d],
+        [0.0, d, d, h - 2.0 * d],
+        [w - d, d, d, h - 2.0 * d]
+    ].iter() {
+        rect.draw(r, &c.draw_state, c.transform, g);
+    }
+}
 
-pub struct BloodText(pub Texture);
-pub struct YouWinText(pub Texture);
-pub struct YouLoseText(pub Texture);
-pub struct PalmTree(pub Texture);
-pub struct SeaBird(pub Texture);
-pub struct Rock(pub Texture);
-pub struct Character(pub Texture);
-
-pub fn render(c: &Context, g: &mut GlGraphics) {
-    use settings::EDIT;
-
-    if EDIT { stream_arrows(c, g); }
-    moving_arrows(c, g);
-    blood(c, g);
-    beach(c, g);
-    rocks(c, g);
-    palm_tree(c, g);
-    player(c, g);
-    sea_birds(c, g);
-    blood_bar(c, g);
-    you_win(c, g);
-    you_lose(c, g);
-}
-
-pub fn stream_arrows(c: &Context, g: &mut GlGraphics) {
-    use current_stream;
+pub fn stream_arrows(w: &World, c: &Context, g: &mut GlGraphics) {
     use graphics;
-    use settings::stream::{ ARROW_COLOR, ARROW_SIZE, SPEEDUP };
+    use settings::stream::{ ARROW_COLOR, ARROW_SIZE };
 
-    let stream = unsafe { &mut *current_stream() };
+    let stream = &w.stream;
+    let speedup = w.config.stream.speedup;
     let line = graphics::Line::new(ARROW_COLOR, 2.0);
     for arrow in stream.arrows.iter() {
-        line.draw_arrow(arrow.line(SPEEDUP), ARROW_SIZE,
+        line.draw_arrow(arrow.line(speedup), ARROW_SIZE,
             &c.draw_state, c.transform, g);
     }
 }
 
-pub fn moving_arrows(c: &Context, g: &mut GlGraphics) {
-    use current_moving_arrows;
+pub fn primitives(w: &World, c: &Context, g: &mut GlGraphics) {
+    use graphics;
+    use settings::primitives::COLOR;
+    use settings::stream::ARROW_SIZE;
+
+    let primitives = &w.stream.primitives;
+    let line = graphics::Line::new(COLOR, 1.0);
+    for x in primitives.whirlpools.iter() {
+        circle(&line, x.pos, x.radius, c, g);
+        // Points the way the water turns at the top.
+        let top = [x.pos[0], x.pos[1] - x.radius];
+        line.draw_arrow([top[0], top[1], top[0] + x.spin, top[1]], ARROW_SIZE,
+            &c.draw_state, c.transform, g);
+    }
+    for x in primitives.outflows.iter() {
+        circle(&line, x.pos, x.radius, c, g);
+        let edge = [x.pos[0] + x.radius, x.pos[1]];
+        line.draw_arrow([edge[0], edge[1], edge[0] + x.strength, edge[1]], ARROW_SIZE,
+            &c.draw_state, c.transform, g);
+    }
+    for x in primitives.jets.iter() {
+        line.draw_arrow([x.start[0], x.start[1], x.end[0], x.end[1]], ARROW_SIZE,
+            &c.draw_state, c.transform, g);
+    }
+}
+
+/// Draws a circle outline with line segments.
+pub fn circle(line: &Line, pos: [f64; 2], radius: f64, c: &Context, g: &mut GlGraphics) {
+    use vecmath::traits::Radians;
+
+    let n = 32;
+    let step = <f64 as Radians>::_360() / n as f64;
+    for i in 0..n {
+        let (a, b) = (i as f64 * step, (i + 1) as f64 * step);
+        line.draw([
+                pos[0] + radius * a.cos(), pos[1] + radius * a.sin(),
+                pos[0] + radius * b.cos(), pos[1] + radius * b.sin()
+            ], &c.draw_state, c.transform, g);
+    }
+}
+
+/// Marks the objects of the current editor tool, and the grabbed one twice.
+pub fn editor_objects(w: &World, c: &Context, g: &mut GlGraphics) {
+    use editor::{ self, Tool };
+    use settings::editor::{ PICK_RADIUS, TOOL_COLOR };
+    use stream::SelectedArrow;
+
+    let line = Line::new(TOOL_COLOR, 1.0);
+    let radius = match w.editor.tool {
+            Tool::Rocks => w.config.rocks.radius,
+            _ => PICK_RADIUS,
+        };
+    let grabbed = match (w.editor.tool, w.selected_arrow) {
+            (Tool::Arrows, SelectedArrow(selected)) => selected.map(|(id, _)| id),
+            _ => w.editor.grabbed,
+        };
+    for (i, &pos) in editor::positions(w, w.editor.tool).iter().enumerate() {
+        circle(&line, pos, radius, c, g);
+        if grabbed == Some(i) {
+            circle(&line, pos, radius + 3.0, c, g);
+        }
+    }
+}
+
+pub fn moving_arrows(w: &World, c: &Context, g: &mut GlGraphics) {
     use graphics::Line;
-    use settings::stream::{
-        MOVING_ARROW_COLOR, 
-        MOVING_ARROW_TIME_SPAN,
-        SPEEDUP,
-    };
+    use settings::stream::MOVING_ARROW_COLOR;
 
-    let moving_arrows = unsafe { &mut *current_moving_arrows() };
+    let moving_arrows = &w.moving_arrows;
+    let speedup = w.config.stream.speedup;
+    let time_span = w.config.stream.moving_arrow_time_span;
     let mut line = Line::new(MOVING_ARROW_COLOR, 1.0);
     for moving_arrow in moving_arrows.iter() {
         let line_color = line.color;
         let red = line_color[0];
         let green = line_color[1];
         let blue = line_color[2];
-        let f = 2.0 * (moving_arrow.time / MOVING_ARROW_TIME_SPAN - 0.5);
+        let f = 2.0 * (moving_arrow.time / time_span - 0.5);
         let alpha = 1.0 - f * f;
         line.color = [red, green, blue, alpha as f32];
-        // line.draw_arrow(moving_arrow.arrow.line(SPEEDUP), ARROW_SIZE, c, g);
-        line.draw(moving_arrow.arrow.line(SPEEDUP),
+        // line.draw_arrow(moving_arrow.arrow.line(speedup), ARROW_SIZE, c, g);
+        line.draw(moving_arrow.arrow.line(speedup),
             &c.draw_state, c.transform, g);
     }
 }
 
-pub fn beach(c: &Context, g: &mut GlGraphics) {
+pub fn beach(w: &World, c: &Context, g: &mut GlGraphics) {
     use graphics::Ellipse;
-    use settings::{ beach_color, BEACH_ELLIPSE };
+    use settings::beach_color;
 
-    Ellipse::new(beach_color()).draw(BEACH_ELLIPSE,
+    Ellipse::new(beach_color()).draw(w.beach,
         &c.draw_state, c.transform, g);
 }
 
-pub fn player(c: &Context, g: &mut GlGraphics) {
-    use current_player;
-    use current_character;
+pub fn player(w: &World, t: &Textures, c: &Context, g: &mut GlGraphics) {
     use graphics::{ Image, Transformed };
     use interpolation::lerp;
     use player::State;
-    use settings::player::{ 
-        FRAMES, BITTEN_COLOR, BITTEN_FADE_OUT_SECONDS,
-    };
+    use settings::player::{ FRAMES, BITTEN_COLOR };
 
-    let &mut Character(ref texture ) = unsafe { &mut *current_character() };
-    let player = unsafe { &mut *current_player() };
+    let texture = &t.character;
+    let player = &w.player;
     let (x, y) = (player.pos[0], player.pos[1]);
     // Rectangle::new(TEST_COLOR).draw(centered_square(x, y, radius), c, g);
     let frame = FRAMES[player.frame];
     match player.state {
         State::Bitten(sec) => {
-            let t = 1.0 - sec / BITTEN_FADE_OUT_SECONDS;
+            let t = 1.0 - sec / w.config.player.bitten_fade_out_seconds;
             let color = lerp(&BITTEN_COLOR, &[1.0; 4], &(t as f32));
             Image::new_colored(color)
         }
@@ -102,17 +187,15 @@ pub fn player(c: &Context, g: &mut GlGraphics) {
     );
 }
 
-pub fn rocks(c: &Context, g: &mut GlGraphics) {
-    use current_rocks;
-    use current_rock;
+pub fn rocks(w: &World, t: &Textures, c: &Context, g: &mut GlGraphics) {
     use graphics::image;
     use graphics::Transformed;
     use graphics::ImageSize;
 
-    let &mut Rock(ref texture) = unsafe { &mut *current_rock() };
+    let texture = &t.rock;
+    let rocks = &w.rocks;
     let (w, h) = texture.get_size();
     let (w, h) = (w as f64, h as f64);
-    let rocks = unsafe { &mut *current_rocks() };
     // let ellipse = Ellipse::new(TEST_COLOR);
     for rock in rocks.rocks.iter() {
         let (x, y) = (rock.pos[0], rock.pos[1]);
@@ -121,9 +204,7 @@ pub fn rocks(c: &Context, g: &mut GlGraphics) {
     }
 }
 
-pub fn blood_bar(c: &Context, g: &mut GlGraphics) {
-    use current_blood_text;
-    use current_blood_bar;
+pub fn blood_bar(w: &World, t: &Textures, c: &Context, g: &mut GlGraphics) {
     use graphics::image;
     use graphics::Transformed;
     use graphics::Rectangle;
@@ -135,8 +216,8 @@ pub fn blood_bar(c: &Context, g: &mut GlGraphics) {
     };
     use blood_bar::BloodBar;
 
-    let &mut BloodText(ref blood_text) = unsafe { &mut *current_blood_text() };
-    let &mut BloodBar(bar) = unsafe { &mut *current_blood_bar() };
+    let blood_text = &t.blood_text;
+    let BloodBar(bar) = w.blood_bar;
     
     let pos = TEXT_POS;
     let zoom = ZOOM;
@@ -156,43 +237,37 @@ pub fn blood_bar(c: &Context, g: &mut GlGraphics) {
         &c.draw_state, c.transform, g);
 }
 
-pub fn you_win(c: &Context, g: &mut GlGraphics) {
-    use current_game_state;
-    use current_you_win_text;
+pub fn you_win(w: &World, t: &Textures, c: &Context, g: &mut GlGraphics) {
     use game::GameState;
     use graphics::image;
     use graphics::Transformed;
     use settings::you_win::{ POS, ZOOM };
 
-    let game_state = unsafe { *current_game_state() };
-    if game_state != GameState::Win { return; }
+    if w.game_state != GameState::Win { return; }
 
-    let &mut YouWinText(ref texture) = unsafe { &mut *current_you_win_text() };
+    let texture = &t.you_win_text;
     image(texture, c.transform.trans(POS[0], POS[1]).zoom(ZOOM), g);
 }
 
-pub fn you_lose(c: &Context, g: &mut GlGraphics) {
-    use current_game_state;
-    use current_you_lose_text;
+pub fn you_lose(w: &World, t: &Textures, c: &Context, g: &mut GlGraphics) {
     use game::GameState;
     use graphics::image;
     use graphics::Transformed;
     use settings::you_lose::{ POS, ZOOM };
 
-    let game_state = unsafe { *current_game_state() };
-    if game_state != GameState::Lose { return; }
+    if w.game_state != GameState::Lose { return; }
 
-    let &mut YouLoseText(ref texture) = unsafe { &mut *current_you_lose_text() };
+    let texture = &t.you_lose_text;
     image(texture, c.transform.trans(POS[0], POS[1]).zoom(ZOOM), g);
 }
 
-pub fn blood(c: &Context, g: &mut GlGraphics) {
-    use current_blood;
+pub fn blood(w: &World, c: &Context, g: &mut GlGraphics) {
     use graphics::Ellipse;
     use graphics::ellipse::circle;
-    use settings::blood::{ test_color, RADIUS, SPAN, START_RADIUS };
+    use settings::blood::{ test_color, RADIUS, START_RADIUS };
 
-    let blood = unsafe { &mut *current_blood() };
+    let blood = &w.blood;
+    let span = w.config.blood.span;
 
     let color = test_color();
     let red = color[0];
@@ -200,7 +275,7 @@ pub fn blood(c: &Context, g: &mut GlGraphics) {
     let blue = color[2];
     for blood_drop in blood.blood_drops.iter().filter(|e| !e.dead) {
         let (x, y) = (blood_drop.pos[0], blood_drop.pos[1]);
-        let f = blood_drop.time / SPAN;
+        let f = blood_drop.time / span;
         let radius = START_RADIUS + (RADIUS - START_RADIUS) * f;
         let alpha = 1.0 - f;
         Ellipse::new([red, green, blue, alpha as f32]).draw(circle(x, y, radius),
@@ -208,28 +283,24 @@ pub fn blood(c: &Context, g: &mut GlGraphics) {
     }
 }
 
-pub fn palm_tree(c: &Context, g: &mut GlGraphics) {
-    use current_palm_tree;
-    use current_palm_trees;
+pub fn palm_tree(w: &World, t: &Textures, c: &Context, g: &mut GlGraphics) {
     use graphics::image;
     use graphics::Transformed;
 
-    let &mut PalmTree(ref texture) = unsafe { &mut *current_palm_tree() };
-    let palm_trees = unsafe { &mut *current_palm_trees() };
+    let texture = &t.palm_tree;
+    let palm_trees = &w.palm_trees;
 
     for pos in palm_trees.palms.iter() {
         image(texture, c.transform.trans(pos[0], pos[1]), g);
     }
 }
 
-pub fn sea_birds(c: &Context, g: &mut GlGraphics) {
-    use current_sea_birds;
-    use current_sea_bird;
+pub fn sea_birds(w: &World, t: &Textures, c: &Context, g: &mut GlGraphics) {
     use graphics::image;
     use graphics::Transformed;
 
-    let sea_birds = unsafe { &mut *current_sea_birds() };
-    let &mut SeaBird(ref texture) = unsafe { &mut *current_sea_bird() };
+    let sea_birds = &w.sea_birds;
+    let texture = &t.sea_bird;
 
     // let rect = Rectangle::new(TEST_COLOR);
     for sea_bird in sea_birds.birds.iter() {
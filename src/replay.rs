@@ -0,0 +1,282 @@
+//! Records inputs and plays them back through the same update path
+//!
+//! A replay file starts with the level, the seed
+//! and every tuning value it was recorded with,
+//! followed by one input per line:
+//!
+//! ```text
+//! level ./assets/levels/01.json
+//! seed 1234
+//! config integrator = euler
+//! config player.acc = 50
+//! press right
+//! update 0.016667
+//! release right
+//! ```
+
+use std::fs::File;
+use std::io::{ self, Read, Write };
+use std::path::{ Path, PathBuf };
+
+use config::Config;
+use player::{ self, KeyState };
+use world::World;
+
+/// One input to the simulation.
+#[derive(Copy, Clone, PartialEq)]
+pub enum ReplayEvent {
+    /// Advance the simulation with a time step.
+    Update(f64),
+    /// A movement key went down.
+    Press(KeyState),
+    /// A movement key went up.
+    Release(KeyState),
+}
+
+impl ReplayEvent {
+    pub fn apply(&self, w: &mut World) {
+        match *self {
+            ReplayEvent::Update(dt) => w.update(dt),
+            ReplayEvent::Press(key) => w.player.key_state.insert(key),
+            ReplayEvent::Release(key) => w.player.key_state.remove(key),
+        }
+    }
+}
+
+/// Everything needed to reproduce a play session.
+#[derive(Clone)]
+pub struct Replay {
+    pub level: PathBuf,
+    pub seed: u32,
+    /// The tuning values, left out in replays from before they were stored.
+    pub config: Option<Config>,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    /// Starts an empty recording.
+    pub fn new(level: &Path, seed: u32, config: &Config) -> Replay {
+        Replay {
+            level: level.to_path_buf(),
+            seed: seed,
+            config: Some(config.clone()),
+            events: Vec::new(),
+        }
+    }
+
+    /// Applies an input to the world and records it.
+    pub fn record(&mut self, w: &mut World, event: ReplayEvent) {
+        self.events.push(event);
+        event.apply(w);
+    }
+
+    pub fn parse(data: &str) -> Result<Replay, String> {
+        let mut level = None;
+        let mut seed = None;
+        let mut config: Option<Config> = None;
+        let mut events = Vec::new();
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.len() == 0 || line.starts_with("#") { continue; }
+
+            let (command, value) = match line.find(' ') {
+                None => (line, ""),
+                Some(x) => (&line[..x], line[x + 1..].trim())
+            };
+            let key = |value: &str| match player::parse_key(value) {
+                    None => Err(format!("line {}: unknown key `{}`", i + 1, value)),
+                    Some(x) => Ok(x)
+                };
+            match command {
+                "level" => level = Some(PathBuf::from(value)),
+                "seed" => seed = match value.parse() {
+                        Ok(x) => Some(x),
+                        Err(_) => {
+                            return Err(format!("line {}: invalid seed `{}`",
+                                i + 1, value));
+                        }
+                    },
+                "config" => {
+                    let mut values = config.take().unwrap_or(Config::new());
+                    try!(values.parse_line(value)
+                        .map_err(|err| format!("line {}: {}", i + 1, err)));
+                    config = Some(values);
+                }
+                "update" => match value.parse() {
+                        Ok(dt) => events.push(ReplayEvent::Update(dt)),
+                        Err(_) => {
+                            return Err(format!("line {}: invalid time step `{}`",
+                                i + 1, value));
+                        }
+                    },
+                "press" => events.push(ReplayEvent::Press(try!(key(value)))),
+                "release" => events.push(ReplayEvent::Release(try!(key(value)))),
+                x => {
+                    return Err(format!("line {}: unknown command `{}`", i + 1, x));
+                }
+            }
+        }
+        match (level, seed) {
+            (Some(level), Some(seed)) => Ok(Replay {
+                level: level,
+                seed: seed,
+                config: config,
+                events: events,
+            }),
+            (None, _) => Err("missing `level` line".to_string()),
+            (_, None) => Err("missing `seed` line".to_string()),
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Replay, String> {
+        let mut data = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut data)) {
+            Ok(_) => {}
+            Err(err) => {
+                return Err(format!("{}: {}", path.display(), err));
+            }
+        }
+        Replay::parse(&data).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut file = try!(File::create(path));
+        try!(writeln!(file, "level {}", self.level.display()));
+        try!(writeln!(file, "seed {}", self.seed));
+        if let Some(ref config) = self.config {
+            for line in config.to_string().lines() {
+                try!(writeln!(file, "config {}", line));
+            }
+        }
+        for event in self.events.iter() {
+            try!(match *event {
+                ReplayEvent::Update(dt) => writeln!(file, "update {}", dt),
+                ReplayEvent::Press(key) =>
+                    writeln!(file, "press {}", player::key_name(key)),
+                ReplayEvent::Release(key) =>
+                    writeln!(file, "release {}", player::key_name(key)),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Feeds a replay to the world one frame at a time.
+pub struct Playback {
+    pub replay: Replay,
+    /// The index of the next event to apply.
+    pub next: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay: replay,
+            next: 0,
+        }
+    }
+
+    /// Applies the events up to and including the next update.
+    ///
+    /// Returns `false` when the replay has ended.
+    pub fn next_frame(&mut self, w: &mut World) -> bool {
+        while self.next < self.replay.events.len() {
+            let event = self.replay.events[self.next];
+            self.next += 1;
+            event.apply(w);
+            if let ReplayEvent::Update(_) = event { return true; }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::Path;
+
+    use config::Config;
+    use integrator::Scheme;
+    use level::{ Level, LevelArrow };
+    use player::{ DOWN, LEFT, RIGHT, UP };
+    use world::World;
+    use super::{ Playback, Replay, ReplayEvent };
+
+    const RECT: [f64; 4] = [0.0, 0.0, 640.0, 480.0];
+
+    fn level() -> Level {
+        Level {
+            seed: None,
+            beach: [500.0, -500.0, 1000.0, 1500.0],
+            start_pos: [100.0, 100.0],
+            blood_bar_start: 1.0,
+            strength: [10.0, 50.0],
+            arrows: vec![
+                LevelArrow { pos: [200.0, 150.0], dir: [30.0, 10.0], phase: 0.0,
+                    oscillation: None },
+                LevelArrow { pos: [400.0, 300.0], dir: [-20.0, 25.0], phase: 0.3,
+                    oscillation: None },
+            ],
+            kernel: None,
+            model: None,
+            primitives: None,
+            generate: None,
+            rocks: vec![[300.0, 200.0]],
+            palm_trees: Vec::new(),
+            sea_birds: vec![[250.0, 250.0]],
+            config: None,
+        }
+    }
+
+    #[test]
+    fn save_and_parse() {
+        use super::ReplayEvent::*;
+
+        let mut config = Config::new();
+        config.integrator = Scheme::Rk4;
+        config.set("player.acc", 60.5).unwrap();
+        let mut replay = Replay::new(Path::new("assets/levels/01.json"), 1234, &config);
+        replay.events = vec![Press(RIGHT), Update(1.0 / 60.0), Press(UP),
+            Update(0.1), Release(RIGHT), Update(0.016667)];
+        let path = env::temp_dir().join("seabirdsbreakfast-replay-test.txt");
+        replay.save(&path).unwrap();
+        let loaded = Replay::from_path(&path).unwrap();
+        assert_eq!(loaded.level, replay.level);
+        assert_eq!(loaded.seed, 1234);
+        assert_eq!(loaded.config.unwrap().to_string(), config.to_string());
+        assert!(loaded.events == replay.events);
+    }
+
+    #[test]
+    fn without_config() {
+        let replay = Replay::parse("level test.json\nseed 1\nupdate 0.5\n").unwrap();
+        assert!(replay.config.is_none());
+        assert!(replay.events == vec![ReplayEvent::Update(0.5)]);
+    }
+
+    #[test]
+    fn playback_reproduces_run() {
+        let level = level();
+        let config = Config::new();
+        let mut recorded = World::new(&level, config.clone(), RECT, 7);
+        let mut replay = Replay::new(Path::new("test.json"), 7, &config);
+        let keys = [RIGHT, DOWN, LEFT];
+        for i in 0..600 {
+            let key = keys[(i / 100) % keys.len()];
+            if i % 100 == 0 { replay.record(&mut recorded, ReplayEvent::Press(key)); }
+            if i % 100 == 50 { replay.record(&mut recorded, ReplayEvent::Release(key)); }
+            replay.record(&mut recorded, ReplayEvent::Update(1.0 / 60.0));
+        }
+
+        let mut played = World::new(&level, replay.config.clone().unwrap(), RECT, replay.seed);
+        let mut playback = Playback::new(replay);
+        while playback.next_frame(&mut played) {}
+        assert_eq!(played.player.pos, recorded.player.pos);
+        assert_eq!(played.game_state, recorded.game_state);
+        assert_eq!(played.blood_bar.0, recorded.blood_bar.0);
+        assert_eq!(played.blood.blood_drops.len(), recorded.blood.blood_drops.len());
+        for (a, b) in played.sea_birds.birds.iter().zip(recorded.sea_birds.birds.iter()) {
+            assert_eq!(a.pos, b.pos);
+        }
+    }
+}
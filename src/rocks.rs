@@ -1,3 +1,4 @@
+#[derive(Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct Rock {
     pub pos: [f64; 2],
 }
@@ -1,7 +1,8 @@
 use piston::event::GenericEvent;
 use ai_behavior;
+use world::World;
 
-#[derive(Clone)]
+#[derive(Clone, RustcEncodable, RustcDecodable)]
 pub enum Action {
     /// Circles forever around target pos.
     Circling,
@@ -17,6 +18,7 @@ pub enum Action {
 
 /// Sea birds fly around a target,
 /// and if you get within a radius of the target it will attack you.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
 pub struct SeaBird {
     pub pos: [f64; 2],
     pub dir: [f64; 2],
@@ -84,22 +86,26 @@ impl SeaBirds {
     }
 }
 
-pub fn update_sea_birds<E: GenericEvent>(e: &E) {
-    use current_sea_birds;
-    use current_player;
+pub fn update_sea_birds<E: GenericEvent>(w: &mut World, e: &E) {
     use vecmath::vec2_add as add;
     use vecmath::vec2_scale as scale;
     use vecmath::vec2_sub as sub;
     use vecmath::vec2_len as len;
     use vecmath::vec2_normalized_sub as normalized_sub;
     use vecmath::traits::Radians;
-    use settings::sea_birds::circling;
-    use settings::sea_birds::SPEEDUP;
     use blood_bar;
     use player;
 
-    let sea_birds = unsafe { &mut *current_sea_birds() };
-    let player = unsafe { &mut *current_player() };
+    let World {
+        ref mut sea_birds,
+        ref mut player,
+        ref mut blood_bar,
+        ref config,
+        ..
+    } = *w;
+    let circling = &config.sea_birds.circling;
+    let speedup = config.sea_birds.speedup;
+    let bitten_fade_out_seconds = config.player.bitten_fade_out_seconds;
 
     let _360: f64 = Radians::_360();
     for sea_bird in sea_birds.birds.iter_mut() {
@@ -116,14 +122,14 @@ pub fn update_sea_birds<E: GenericEvent>(e: &E) {
                 Action::Circling => {
                     let angle = *circling_angle;
                     let angle_pos = add(*target, 
-                        scale([angle.cos(), angle.sin()], circling::RADIUS));
+                        scale([angle.cos(), angle.sin()], circling.radius));
                     *dir = normalized_sub(angle_pos, *pos);
-                    *pos = add(*pos, scale(*dir, dt * SPEEDUP * circling::SPEED));
+                    *pos = add(*pos, scale(*dir, dt * speedup * circling.speed));
 
                     let diff = sub(angle_pos, *pos);
                     let diff_len = len(diff);
-                    if diff_len < circling::ADVANCE_RADIUS {
-                        *circling_angle = angle + _360 / circling::N;
+                    if diff_len < circling.advance_radius {
+                        *circling_angle = angle + _360 / circling.n;
                     }
                     
                     (ai_behavior::Running, 0.0)
@@ -146,12 +152,12 @@ pub fn update_sea_birds<E: GenericEvent>(e: &E) {
                 }
                 Action::FlyTowardPlayer => {
                     *dir = normalized_sub(player.pos, *pos);
-                    *pos = add(*pos, scale(*dir, dt * SPEEDUP * circling::SPEED));
+                    *pos = add(*pos, scale(*dir, dt * speedup * circling.speed));
                     (ai_behavior::Running, 0.0)
                 }
                 Action::AttackPlayer(val) => {
-                    player.state = player::State::Bitten(::settings::player::BITTEN_FADE_OUT_SECONDS);
-                    blood_bar::decrease(val);
+                    player.state = player::State::Bitten(bitten_fade_out_seconds);
+                    blood_bar::decrease(blood_bar, val);
                     (ai_behavior::Success, dt)                    
                 }
             }
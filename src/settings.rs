@@ -1,8 +1,10 @@
 use graphics::color::hex;
+use integrator::Scheme;
 
-pub const BEACH_ELLIPSE: [f64; 4] = [500.0, -500.0, 1000.0, 1500.0];
+pub const WINDOW_SIZE: [u32; 2] = [640, 480];
 pub const WATER_FRICTION: f64 = 0.0004;
-pub const EDIT: bool = false;
+// How the player, blood drops and moving arrows follow the current.
+pub const INTEGRATOR: Scheme = Scheme::Euler;
 
 pub fn background_color() -> [f32; 4] {
     hex("49B1DE")
@@ -21,6 +23,68 @@ pub mod utils {
     pub const PRINT_HAS_WON: Button = Button::Keyboard(Key::W);
     pub const PRINT_STREAM: Button = Button::Keyboard(Key::S);
     pub const RESTART_LEVEL: Button = Button::Keyboard(Key::Return);
+    pub const QUICK_SAVE: Button = Button::Keyboard(Key::F5);
+    pub const QUICK_LOAD: Button = Button::Keyboard(Key::F9);
+    pub const QUICK_SAVE_FILE: &'static str = "./quicksave.json";
+}
+
+pub mod editor {
+    use piston::input::Button;
+    use piston::input::keyboard::Key;
+
+    use piston::input::MouseButton;
+
+    pub const TOGGLE: Button = Button::Keyboard(Key::E);
+    pub const ROCK_TOOL: Button = Button::Keyboard(Key::D1);
+    pub const PALM_TREE_TOOL: Button = Button::Keyboard(Key::D2);
+    pub const SEA_BIRD_TOOL: Button = Button::Keyboard(Key::D3);
+    pub const ARROW_TOOL: Button = Button::Keyboard(Key::D4);
+    // Grabs the object under the cursor, or places a new one.
+    pub const GRAB: Button = Button::Mouse(MouseButton::Right);
+    pub const DELETE: Button = Button::Keyboard(Key::Delete);
+    pub const UNDO: Button = Button::Keyboard(Key::Z);
+    pub const REDO: Button = Button::Keyboard(Key::Y);
+    // How many changes can be undone.
+    pub const HISTORY_SIZE: usize = 100;
+    pub const SAVE_LEVEL: Button = Button::Keyboard(Key::F2);
+    pub const RELOAD_LEVEL: Button = Button::Keyboard(Key::F3);
+    // How close to an object the cursor must be to grab or delete it.
+    pub const PICK_RADIUS: f64 = 20.0;
+    pub const TOOL_COLOR: [f32; 4] = [1.0, 0.6, 0.0, 0.8];
+    // A frame around the window shows that the game is paused for editing.
+    pub const FRAME_COLOR: [f32; 4] = [1.0, 0.6, 0.0, 0.8];
+    pub const FRAME_WIDTH: f64 = 4.0;
+}
+
+pub mod bindings {
+    // The user's buttons for each action, created with the defaults.
+    pub const FILE: &'static str = "./bindings.txt";
+}
+
+pub mod config {
+    // Overrides the tuning constants, if the file exists.
+    pub const FILE: &'static str = "./assets/config.txt";
+    // How often to check the config file for changes, in seconds.
+    pub const WATCH_INTERVAL: f64 = 0.5;
+}
+
+pub mod campaign {
+    // Lists the level files in the order they are played.
+    pub const LEVELS: &'static str = "./assets/campaign.txt";
+    // Remembers the highest unlocked level between sessions.
+    pub const PROGRESS: &'static str = "./progress.txt";
+}
+
+pub mod export {
+    // The distance between samples of the exported current.
+    pub const RESOLUTION: f64 = 10.0;
+    pub const ARROW_COLOR: [u8; 3] = [255, 255, 255];
+}
+
+pub mod headless {
+    pub const DT: f64 = 1.0 / 60.0;
+    // Give up after ten minutes of game time.
+    pub const DURATION: f64 = 600.0;
 }
 
 pub mod you_win {
@@ -40,7 +104,6 @@ pub mod player {
 
     pub const SPEEDUP: f64 = 2.0;
     // pub const RADIUS: f64 = 5.0;
-    pub const START_POS: [f64; 2] = [100.0, 100.0];
     // pub const START_VEL: [f64; 2] = [0.0, 0.0];
     // pub const TEST_COLOR: [f32; 4] = [1.0, ..4];
     pub const BITTEN_COLOR: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
@@ -67,9 +130,18 @@ pub mod player {
 
 pub mod stream {
     use piston::input::{ Button, MouseButton };
+    use piston::input::keyboard::Key;
     
     pub const ADD_ARROW_BUTTON: Button = Button::Mouse(MouseButton::Left);
-    pub const STRENGTH: [f64; 2] = [10.0, 50.0];
+    // Change the oscillation of the arrow closest to the cursor.
+    pub const CYCLE_WAVEFORM: Button = Button::Keyboard(Key::O);
+    pub const FASTER_OSCILLATION: Button = Button::Keyboard(Key::Period);
+    pub const SLOWER_OSCILLATION: Button = Button::Keyboard(Key::Comma);
+    pub const FREQUENCY_STEP: f64 = 1.25;
+    pub const PHASE_FORWARD: Button = Button::Keyboard(Key::RightBracket);
+    pub const PHASE_BACK: Button = Button::Keyboard(Key::LeftBracket);
+    // A sixteenth of a round.
+    pub const PHASE_STEP: f64 = 1.0 / 16.0;
     pub const ARROW_COLOR: [f32; 4] = [0.0, 0.0, 0.7, 0.8];
     pub const ARROW_SIZE: f64 = 5.0;
     pub const SAMPLE_SIZE: f64 = 25.0;
@@ -78,73 +150,49 @@ pub mod stream {
     pub const MOVING_ARROW_TIME_SPAN: f64 = 40.0;
     pub const SPEEDUP: f64 = 1.0;
     pub const PHASE_VEL: f64 = 1.0 / 7.0;
-    
-    pub fn load() {
-        use current_stream;
-        use stream::Arrow;
-
-        let stream = unsafe { &mut *current_stream() };
-
-        // Just split by comma.
-        let data = include_str!("../assets/stream.txt");
-        let mut data_split = data.split(",");
-        loop {
-            let x: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            let y: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            let dx: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            let dy: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            let phase: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            stream.add_arrow(Arrow { pos: [x, y], dir: [dx, dy] }, phase);            
-        }
-    }
+    // Arrows further away than this many times the size of their group
+    // are summed up, zero visits every arrow.
+    pub const TREE_THETA: f64 = 0.3;
+    pub const TREE_LEAF_SIZE: usize = 4;
+    // The step of the differences in the incompressible field model.
+    pub const CURL_STEP: f64 = 0.5;
+    // The distance between baked stream samples, zero computes every position.
+    pub const FIELD_RESOLUTION: f64 = 0.0;
+    // Smaller cells would bake more samples per frame than anything reads.
+    pub const MIN_FIELD_RESOLUTION: f64 = 4.0;
+    // Finer noise in generated streams would need too many random angles.
+    pub const MIN_NOISE_SCALE: f64 = 10.0;
+}
+
+pub mod primitives {
+    use piston::input::Button;
+    use piston::input::keyboard::Key;
+
+    // Hold down and move the mouse to set the radius, or the end of a jet.
+    pub const ADD_WHIRLPOOL: Button = Button::Keyboard(Key::V);
+    pub const ADD_OUTFLOW: Button = Button::Keyboard(Key::U);
+    pub const ADD_JET: Button = Button::Keyboard(Key::J);
+    pub const COLOR: [f32; 4] = [0.0, 0.3, 0.7, 0.8];
+    pub const RADIUS: f64 = 60.0;
+    pub const WHIRLPOOL_SPIN: f64 = 30.0;
+    pub const OUTFLOW_STRENGTH: f64 = 20.0;
+    pub const JET_WIDTH: f64 = 20.0;
+    pub const JET_STRENGTH: f64 = 40.0;
 }
 
 pub mod rocks {
     // pub const TEST_COLOR: [f32, ..4] = [0.6, 0.6, 0.6, 1.0];
     pub const RADIUS: f64 = 20.0;
-
-    pub fn load() {
-        use current_rocks;
-        use rocks::Rock;
-
-        let rocks = unsafe { &mut *current_rocks() };
-
-        // Just split by comma.
-        let data = include_str!("../assets/rocks.txt");
-        let mut data_split = data.split(",");
-        loop {
-            let x: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            let y: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            rocks.rocks.push(Rock { pos: [x, y] });            
-        }
-    }
+    // The current is bent around rocks closer than this many radii,
+    // fading out from half of it.
+    pub const INFLUENCE: f64 = 6.0;
+    // How fast the water circles behind a rock, relative to the current.
+    pub const WAKE: f64 = 0.3;
 }
 
 pub mod blood_bar {
     pub const TEXT_POS: [f64; 2] = [567.0, 441.0];
     pub const ZOOM: f64 = 2.0;
-    pub const START_VAL: f64 = 1.0;
     pub const BAR_POS: [f64; 2] = [581.0, 433.0];
     pub const BAR_SIZE: [f64; 2] = [10.0, 200.0];
     pub const ROUND_RADIUS: f64 = 5.0;
@@ -178,29 +226,6 @@ pub mod blood {
     }
 }
 
-pub mod palm_trees {
-    pub fn load() {
-        use current_palm_trees;
-
-        let palm_trees = unsafe { &mut *current_palm_trees() };
-
-        // Just split by comma.
-        let data = include_str!("../assets/palm_trees.txt");
-        let mut data_split = data.split(",");
-        loop {
-            let x: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            let y: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            palm_trees.palms.push([x, y]);            
-        }
-    }
-}
-
 pub mod sea_birds {
     // pub const RADIUS: f64 = 5.0;
     // pub const TEST_COLOR: [f32, ..4] = [1.0, 1.0, 0.0, 1.0];
@@ -214,31 +239,5 @@ pub mod sea_birds {
         pub const SPEED: f64 = 4.0;
         pub const RADIUS: f64 = 50.0;
     }
- 
-    pub fn load() {
-        use current_sea_birds;
-        use sea_birds::SeaBird;
-
-        let sea_birds = unsafe { &mut *current_sea_birds() };
-
-        // Just split by comma.
-        let data = include_str!("../assets/sea_birds.txt");
-        let mut data_split = data.split(",");
-        loop {
-            let x: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            let y: f64 = match data_split.next() {
-                    None => { break; }
-                    Some(x) => x.trim().parse().unwrap()
-                };
-            sea_birds.birds.push(SeaBird::new(
-                    [x, y],
-                    [x, y],
-                    sea_birds.behavior.clone(),
-                ));
-        }
-    }
 }
 
@@ -0,0 +1,123 @@
+//! Saves and restores the complete simulation state
+
+use std::fs::File;
+use std::io::{ Read, Write };
+use std::path::Path;
+use rustc_serialize::json;
+
+use blood::Blood;
+use blood_bar::BloodBar;
+use game::GameState;
+use kernel::Kernel;
+use level::LevelError;
+use player::Player;
+use primitives::{ Primitives, SelectedPrimitive };
+use rocks::Rock;
+use sea_birds::SeaBird;
+use stream::{ Arrow, FieldModel, MovingArrow, Oscillation };
+use world::{ World, XorShift };
+
+/// A copy of everything the simulation reads and writes.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct Snapshot {
+    pub beach: [f64; 4],
+    pub rect: [f64; 4],
+    pub strength: [f64; 2],
+    pub arrows: Vec<Arrow>,
+    pub arrow_phases: Vec<f64>,
+    pub arrow_oscillations: Vec<Oscillation>,
+    pub kernel: Kernel,
+    pub model: FieldModel,
+    pub primitives: Primitives,
+    pub moving_arrows: Vec<MovingArrow>,
+    pub player: Player,
+    pub rocks: Vec<Rock>,
+    pub game_state: GameState,
+    pub blood_bar: f64,
+    pub blood: Blood,
+    pub palm_trees: Vec<[f64; 2]>,
+    pub sea_birds: Vec<SeaBird>,
+    pub seed: u32,
+    /// The random generator continues from this state after the snapshot.
+    pub rng: XorShift,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of the world.
+    ///
+    /// The world is left as it is, so taking a snapshot
+    /// while recording does not change the replay.
+    pub fn take(w: &World) -> Snapshot {
+        let BloodBar(blood_bar) = w.blood_bar;
+        Snapshot {
+            beach: w.beach,
+            rect: w.stream.rect,
+            strength: w.stream.strength,
+            arrows: w.stream.arrows.clone(),
+            arrow_phases: w.stream.arrow_phases.clone(),
+            arrow_oscillations: w.stream.arrow_oscillations.clone(),
+            kernel: w.stream.kernel,
+            model: w.stream.model,
+            primitives: w.stream.primitives.clone(),
+            moving_arrows: w.moving_arrows.clone(),
+            player: w.player.clone(),
+            rocks: w.rocks.rocks.clone(),
+            game_state: w.game_state,
+            blood_bar: blood_bar,
+            blood: w.blood.clone(),
+            palm_trees: w.palm_trees.palms.clone(),
+            sea_birds: w.sea_birds.birds.clone(),
+            seed: w.seed,
+            rng: w.rng.clone(),
+        }
+    }
+
+    /// Puts the world back into the state of the snapshot.
+    pub fn restore(&self, w: &mut World) {
+        use history;
+        use stream::{ self, SelectedArrow };
+
+        w.beach = self.beach;
+        w.stream.rect = self.rect;
+        w.stream.strength = self.strength;
+        w.stream.set_kernel(self.kernel);
+        w.stream.set_model(self.model);
+        w.stream.set_primitives(self.primitives.clone());
+        w.stream.set_arrows(self.arrows.clone(), self.arrow_phases.clone(),
+            self.arrow_oscillations.clone());
+        w.moving_arrows = self.moving_arrows.clone();
+        w.player = self.player.clone();
+        w.rocks.rocks = self.rocks.clone();
+        stream::refresh_obstacles(w);
+        w.selected_arrow = SelectedArrow(None);
+        w.selected_primitive = SelectedPrimitive(None);
+        w.editor.grabbed = None;
+        history::clear(w);
+        w.game_state = self.game_state;
+        w.blood_bar = BloodBar(self.blood_bar);
+        w.blood = self.blood.clone();
+        w.palm_trees.palms = self.palm_trees.clone();
+        w.sea_birds.birds = self.sea_birds.clone();
+        w.seed = self.seed;
+        w.rng = self.rng.clone();
+    }
+
+    /// Reads a snapshot from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Snapshot, LevelError> {
+        let mut data = String::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_string(&mut data))
+            .map_err(|err| LevelError::Io(path.to_path_buf(), err)));
+        let snapshot: Snapshot = try!(json::decode(&data)
+            .map_err(|err| LevelError::Decode(path.to_path_buf(), err)));
+        try!(snapshot.kernel.validate()
+            .map_err(|err| LevelError::Invalid(path.to_path_buf(), format!("kernel: {}", err))));
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot to a JSON file.
+    pub fn save(&self, path: &Path) -> Result<(), LevelError> {
+        let data = format!("{}\n", json::as_json(self));
+        File::create(path).and_then(|mut f| f.write_all(data.as_bytes()))
+            .map_err(|err| LevelError::Io(path.to_path_buf(), err))
+    }
+}
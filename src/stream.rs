@@ -1,10 +1,26 @@
 //! Describes the sea current
 
+use arrow_tree::ArrowTree;
+use flow_field::FlowField;
+use kernel::Kernel;
+use obstacles::Obstacles;
+use primitives::Primitives;
+use world::World;
+
+/// The part of an arrow that follows the cursor.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ArrowHandle {
+    /// Moves the arrow.
+    Base,
+    /// Aims the arrow.
+    Tip,
+}
+
 /// The current selected arrow.
 #[derive(Copy, Clone)]
-pub struct SelectedArrow(pub Option<usize>);
+pub struct SelectedArrow(pub Option<(usize, ArrowHandle)>);
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct Arrow {
     pub pos: [f64; 2],
     pub dir: [f64; 2],
@@ -21,6 +37,74 @@ impl Arrow {
     }
 }
 
+/// How the arrows make up the current.
+#[derive(Copy, Clone, PartialEq, Debug, RustcEncodable, RustcDecodable)]
+pub enum FieldModel {
+    /// Weighted average of the arrows.
+    Average,
+    /// Flow of the weighted average of the arrow stream functions.
+    /// Water neither piles up nor vanishes in the arrow current,
+    /// but the primitives are added on top and the rocks bend the sum,
+    /// so outflows and the water around rocks do not keep this.
+    Incompressible,
+}
+
+/// The shape an arrow strength follows over one round of its phase.
+#[derive(Copy, Clone, PartialEq, Debug, RustcEncodable, RustcDecodable)]
+pub enum Waveform {
+    Sine,
+    /// A square wave with soft edges.
+    Pulse,
+    /// Ignores the phase.
+    Constant,
+}
+
+/// How the strength of an arrow changes over time.
+#[derive(Copy, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Oscillation {
+    /// How fast the phase advances, relative to the stream phase velocity.
+    pub frequency: f64,
+    pub amplitude: f64,
+    /// Added to the wave, so the arrow can keep one direction.
+    pub offset: f64,
+    pub waveform: Waveform,
+}
+
+impl Waveform {
+    pub fn next(self) -> Waveform {
+        match self {
+            Waveform::Sine => Waveform::Pulse,
+            Waveform::Pulse => Waveform::Constant,
+            Waveform::Constant => Waveform::Sine,
+        }
+    }
+}
+
+impl Oscillation {
+    /// A sine wave at the stream phase velocity, swinging between -1 and 1.
+    pub fn new() -> Oscillation {
+        Oscillation {
+            frequency: 1.0,
+            amplitude: 1.0,
+            offset: 0.0,
+            waveform: Waveform::Sine,
+        }
+    }
+
+    /// How much the arrow counts at a phase.
+    pub fn factor(&self, phase: f64) -> f64 {
+        // How steep the pulse edges are.
+        let steepness = 4.0;
+        let wave = match self.waveform {
+                Waveform::Sine => phase.cos(),
+                Waveform::Pulse => (steepness * phase.cos()).tanh() / steepness.tanh(),
+                Waveform::Constant => 1.0,
+            };
+        self.offset + self.amplitude * wave
+    }
+}
+
+#[derive(Clone, RustcEncodable, RustcDecodable)]
 pub struct MovingArrow {
     pub arrow: Arrow,
     pub start_pos: [f64; 2],
@@ -36,34 +120,264 @@ pub struct Stream {
     pub strength: [f64; 2],
     /// The phase of the arrow the moment they were added.
     pub arrow_phases: Vec<f64>,
+    /// How the strength of each arrow changes with its phase.
+    pub arrow_oscillations: Vec<Oscillation>,
+    /// How arrows are blended.
+    pub kernel: Kernel,
+    pub model: FieldModel,
+    /// Whirlpools, outflows and jets added on top of the arrows.
+    pub primitives: Primitives,
+    /// Rocks the current flows around.
+    pub obstacles: Obstacles,
+    /// Index over the arrows, kept in sync by the methods below.
+    pub tree: ArrowTree,
+    /// Samples baked by `update`, cleared when the arrows change.
+    pub field: Option<FlowField>,
 }
 
 impl Stream {
-    pub fn add_arrow(&mut self, arrow: Arrow, phase: f64) {
+    pub fn new(
+        rect: [f64; 4],
+        strength: [f64; 2],
+        kernel: Kernel,
+        model: FieldModel
+    ) -> Stream {
+        Stream {
+            arrows: Vec::new(),
+            rect: rect,
+            strength: strength,
+            arrow_phases: Vec::new(),
+            arrow_oscillations: Vec::new(),
+            kernel: kernel,
+            model: model,
+            primitives: Primitives::new(),
+            obstacles: Obstacles::new(),
+            tree: ArrowTree::new(&[]),
+            field: None,
+        }
+    }
+
+    pub fn add_arrow(&mut self, arrow: Arrow, phase: f64, oscillation: Oscillation) {
         self.arrows.push(arrow);
         self.arrow_phases.push(phase);
+        self.arrow_oscillations.push(oscillation);
+        self.rebuild_tree();
     }
-    
-    /// Computes stream at position using weighted average
-    pub fn at(&self, pos: [f64; 2]) -> [f64; 2] {
-        use vecmath::vec2_add as add;
+
+    /// Replaces all arrows, such as when restoring a snapshot.
+    pub fn set_arrows(
+        &mut self,
+        arrows: Vec<Arrow>,
+        arrow_phases: Vec<f64>,
+        arrow_oscillations: Vec<Oscillation>
+    ) {
+        self.arrows = arrows;
+        self.arrow_phases = arrow_phases;
+        self.arrow_oscillations = arrow_oscillations;
+        self.rebuild_tree();
+    }
+
+    /// Puts an arrow in front of the arrow at `id`.
+    pub fn insert_arrow(
+        &mut self,
+        id: usize,
+        arrow: Arrow,
+        phase: f64,
+        oscillation: Oscillation
+    ) {
+        self.arrows.insert(id, arrow);
+        self.arrow_phases.insert(id, phase);
+        self.arrow_oscillations.insert(id, oscillation);
+        self.rebuild_tree();
+    }
+
+    /// Replaces an arrow with its phase and oscillation.
+    pub fn set_arrow(
+        &mut self,
+        id: usize,
+        arrow: Arrow,
+        phase: f64,
+        oscillation: Oscillation
+    ) {
+        self.arrows[id] = arrow;
+        self.arrow_phases[id] = phase;
+        self.arrow_oscillations[id] = oscillation;
+        self.rebuild_tree();
+    }
+
+    /// Removes an arrow with its phase and oscillation.
+    pub fn remove_arrow(&mut self, id: usize) {
+        self.arrows.remove(id);
+        self.arrow_phases.remove(id);
+        self.arrow_oscillations.remove(id);
+        self.rebuild_tree();
+    }
+
+    /// Moves an arrow.
+    pub fn set_pos(&mut self, id: usize, pos: [f64; 2]) {
+        self.arrows[id].pos = pos;
+        self.rebuild_tree();
+    }
+
+    /// Changes where an arrow is in its oscillation.
+    pub fn set_phase(&mut self, id: usize, phase: f64) {
+        self.arrow_phases[id] = phase;
+        self.refresh_tree();
+    }
+
+    /// Finds the arrow part within `radius` of a position.
+    ///
+    /// Tips are picked before the rest of an arrow,
+    /// so short arrows can still be aimed.
+    pub fn hit_arrow(&self, pos: [f64; 2], radius: f64, speedup: f64)
+        -> Option<(usize, ArrowHandle)>
+    {
         use vecmath::vec2_sub as sub;
         use vecmath::vec2_len as len;
+        use vecmath::vec2_dot as dot;
         use vecmath::vec2_scale as scale;
+        use vecmath::vec2_add as add;
 
-        if self.arrows.len() == 0 { return [0.0; 2]; }
-
-        let mut sum = [0.0; 2];
-        let mut sum_w = 0.0;
+        let mut best: Option<(usize, ArrowHandle, f64)> = None;
         for (i, arrow) in self.arrows.iter().enumerate() {
-            let l = len(sub(arrow.pos, pos)).max(0.01);
-            let w = 1.0 / (l * l);
-            let phase_w = self.arrow_phases[i].cos();
-            sum = add(sum, scale(arrow.dir, w * phase_w));
-            sum_w += w;
+            let line = arrow.line(speedup);
+            let tip = [line[2], line[3]];
+            let tip_dist = len(sub(pos, tip));
+            let hit = if tip_dist <= radius {
+                    Some((ArrowHandle::Tip, tip_dist))
+                } else {
+                    let d = sub(tip, arrow.pos);
+                    let l = dot(d, d);
+                    let t = if l > 0.0 {
+                            (dot(sub(pos, arrow.pos), d) / l).max(0.0).min(1.0)
+                        } else {
+                            0.0
+                        };
+                    let dist = len(sub(pos, add(arrow.pos, scale(d, t))));
+                    if dist <= radius { Some((ArrowHandle::Base, dist)) } else { None }
+                };
+            if let Some((handle, dist)) = hit {
+                let better = match best {
+                        None => true,
+                        Some((_, best_handle, best_dist)) =>
+                            (handle == ArrowHandle::Tip && best_handle == ArrowHandle::Base)
+                            || (handle == best_handle && dist < best_dist)
+                    };
+                if better { best = Some((i, handle, dist)); }
+            }
         }
+        best.map(|(i, handle, _)| (i, handle))
+    }
+
+    /// Changes the direction of an arrow.
+    pub fn set_dir(&mut self, id: usize, dir: [f64; 2]) {
+        self.arrows[id].dir = dir;
+        self.refresh_tree();
+    }
+
+    /// Changes how the strength of an arrow changes over time.
+    pub fn set_oscillation(&mut self, id: usize, oscillation: Oscillation) {
+        self.arrow_oscillations[id] = oscillation;
+        self.refresh_tree();
+    }
+
+    /// Finds the arrow closest to a position.
+    pub fn closest_arrow(&self, pos: [f64; 2]) -> Option<usize> {
+        if self.arrows.len() == 0 { return None; }
+        self.tree.nearest(&self.arrows, &Kernel::new(), pos)
+    }
+
+    /// How much each arrow counts at its current phase.
+    pub fn factors(&self) -> Vec<f64> {
+        self.arrow_oscillations.iter().zip(self.arrow_phases.iter())
+            .map(|(oscillation, &phase)| oscillation.factor(phase))
+            .collect()
+    }
 
-        scale(sum, 1.0 / sum_w)
+    /// Must be called when arrow directions or strengths change.
+    fn refresh_tree(&mut self) {
+        let factors = self.factors();
+        self.tree.refresh(&self.arrows, factors);
+        self.refresh_obstacles();
+    }
+
+    pub fn set_kernel(&mut self, kernel: Kernel) {
+        self.kernel = kernel;
+        self.field = None;
+    }
+
+    pub fn set_primitives(&mut self, primitives: Primitives) {
+        self.primitives = primitives;
+        self.refresh_obstacles();
+    }
+
+    /// Changes the rocks the current flows around.
+    pub fn set_obstacles(&mut self, centers: Vec<[f64; 2]>, radius: f64) {
+        self.obstacles.centers = centers;
+        self.obstacles.radius = radius;
+        self.refresh_obstacles();
+    }
+
+    /// Samples the current at the rocks again, after it changed.
+    fn refresh_obstacles(&mut self) {
+        let mut obstacles = self.obstacles.clone();
+        obstacles.refresh(|pos| self.at_free(pos));
+        self.obstacles = obstacles;
+        self.field = None;
+    }
+
+    pub fn set_model(&mut self, model: FieldModel) {
+        self.model = model;
+        self.field = None;
+    }
+
+    /// Must be called when arrows are added, removed or moved.
+    pub fn rebuild_tree(&mut self) {
+        self.tree = ArrowTree::new(&self.arrows);
+        self.refresh_tree();
+    }
+
+    /// Computes stream at position,
+    /// from the baked samples when there are any.
+    pub fn at(&self, pos: [f64; 2]) -> [f64; 2] {
+        if let Some(ref field) = self.field {
+            if let Some(dir) = field.at(pos) { return dir; }
+        }
+        self.at_unbaked(pos)
+    }
+
+    /// Computes stream at position from the arrows and primitives,
+    /// flowing around the rocks.
+    ///
+    /// The rocks bend the current after it is computed,
+    /// so close to them it is not free of divergence with either model.
+    pub fn at_unbaked(&self, pos: [f64; 2]) -> [f64; 2] {
+        self.obstacles.deflect(pos, self.at_free(pos))
+    }
+
+    /// Computes stream at position from the arrows and primitives,
+    /// as if there were no rocks.
+    pub fn at_free(&self, pos: [f64; 2]) -> [f64; 2] {
+        use vecmath::vec2_add as add;
+
+        add(self.at_arrows(pos), self.primitives.at(pos))
+    }
+
+    /// Computes stream at position from the arrows.
+    ///
+    /// Groups of far away arrows are summed up,
+    /// see `ArrowTree::gather` for how close this stays to visiting every arrow.
+    pub fn at_arrows(&self, pos: [f64; 2]) -> [f64; 2] {
+        use settings::stream::{ CURL_STEP, TREE_THETA };
+
+        if self.arrows.len() == 0 { return [0.0; 2]; }
+
+        match self.model {
+            FieldModel::Average =>
+                self.tree.at(&self.arrows, &self.kernel, pos, TREE_THETA),
+            FieldModel::Incompressible =>
+                self.tree.curl(&self.arrows, &self.kernel, pos, TREE_THETA, CURL_STEP),
+        }
     }
 
     pub fn nm(&self, sample_size: f64) -> [u32; 2] {
@@ -71,68 +385,131 @@ impl Stream {
         [(w / sample_size) as u32, (h / sample_size) as u32]
     }
 
-    pub fn update(&mut self, dt: f64) {
-        use settings::stream::PHASE_VEL;
+    /// Advances the phases, `phase_vel` is in rounds per second
+    /// and is scaled by the frequency of each arrow.
+    ///
+    /// Bakes the samples used by `at` when `field_resolution`,
+    /// the distance between samples, is above zero.
+    /// The whole rectangle is baked every frame, about
+    /// `(width / field_resolution + 1) * (height / field_resolution + 1)` samples,
+    /// which only pays off when more positions than that are read per frame.
+    pub fn update(&mut self, dt: f64, phase_vel: f64, field_resolution: f64) {
         use vecmath::traits::Radians;
        
-        let shift: f64 = dt * PHASE_VEL * <f64 as Radians>::_360();
-        for arrow_phase in self.arrow_phases.iter_mut() {
-            *arrow_phase += shift;
+        let shift: f64 = dt * phase_vel * <f64 as Radians>::_360();
+        for (arrow_phase, oscillation) in self.arrow_phases.iter_mut()
+            .zip(self.arrow_oscillations.iter()) {
+            *arrow_phase += shift * oscillation.frequency;
         }
+        self.refresh_tree();
+        let field = if field_resolution > 0.0 {
+                Some(FlowField::bake(self.rect, field_resolution,
+                    |pos| self.at_unbaked(pos)))
+            } else {
+                None
+            };
+        self.field = field;
     }
 
 }
 
-pub fn add_arrow(pos: [f64; 2]) {
-    use current_stream;
-    use current_selected_arrow;
-    use rand::{ Rand, thread_rng };
+pub fn add_arrow(w: &mut World, pos: [f64; 2]) {
+    use rand::Rand;
     use vecmath::traits::Radians;
 
-    let stream = unsafe { &mut *current_stream() };
-    let selected_arrow = unsafe { &mut *current_selected_arrow() };
+    let World {
+        ref mut stream,
+        ref mut selected_arrow,
+        ref mut rng,
+        ..
+    } = *w;
 
     stream.add_arrow(
         Arrow {
             pos: pos,
             dir: [0.0; 2],
         },
-        <f64 as Rand>::rand(&mut thread_rng()) * <f64 as Radians>::_360()
+        <f64 as Rand>::rand(rng) * <f64 as Radians>::_360(),
+        Oscillation::new()
     );
 
     let id = stream.arrows.len() - 1;
-    *selected_arrow = SelectedArrow(Some(id));
+    *selected_arrow = SelectedArrow(Some((id, ArrowHandle::Tip)));
 }
 
-pub fn edit_selected_arrow(pos: [f64; 2]) {
-    use current_stream;
-    use current_selected_arrow;
+/// Selects the part of an arrow under the cursor.
+///
+/// Returns `false` if there is no arrow there.
+pub fn select_arrow(w: &mut World, pos: [f64; 2]) -> bool {
+    use settings::editor::PICK_RADIUS;
+
+    let speedup = w.config.stream.speedup;
+    match w.stream.hit_arrow(pos, PICK_RADIUS, speedup) {
+        None => false,
+        Some(x) => {
+            w.selected_arrow = SelectedArrow(Some(x));
+            true
+        }
+    }
+}
+
+/// Moves the selected arrow, or aims it at a position.
+pub fn edit_selected_arrow(w: &mut World, pos: [f64; 2]) {
     use vecmath::vec2_sub as sub;
     use vecmath::vec2_scale as scale;
-    use settings::stream::SPEEDUP;
 
-    let stream = unsafe { &mut *current_stream() };
-    let &mut SelectedArrow(selected_arrow) = unsafe { &mut *current_selected_arrow() };
-    let id = match selected_arrow {
-        None => { return; }
-        Some(x) => x
-    };
-    stream.arrows[id].dir = scale(sub(pos, stream.arrows[id].pos), 1.0 / SPEEDUP);
+    let SelectedArrow(selected_arrow) = w.selected_arrow;
+    let speedup = w.config.stream.speedup;
+    let stream = &mut w.stream;
+    match selected_arrow {
+        None => {}
+        Some((id, ArrowHandle::Base)) => stream.set_pos(id, pos),
+        Some((id, ArrowHandle::Tip)) => {
+            let dir = scale(sub(pos, stream.arrows[id].pos), 1.0 / speedup);
+            stream.set_dir(id, dir);
+        }
+    }
 }
 
-pub fn deselect_arrow() {
-    use current_selected_arrow;
+/// Shifts the phase of the arrow closest to a position, in rounds.
+pub fn shift_phase(w: &mut World, pos: [f64; 2], rounds: f64) {
+    use vecmath::traits::Radians;
 
-    *unsafe { current_selected_arrow() } = SelectedArrow(None);
+    let stream = &mut w.stream;
+    if let Some(id) = stream.closest_arrow(pos) {
+        let phase = stream.arrow_phases[id] + rounds * <f64 as Radians>::_360();
+        stream.set_phase(id, phase);
+    }
 }
 
-pub fn refresh_moving_arrows() {
-    use current_stream;
-    use current_moving_arrows;
+/// Switches the arrow closest to a position to the next waveform.
+pub fn cycle_waveform(w: &mut World, pos: [f64; 2]) {
+    let stream = &mut w.stream;
+    if let Some(id) = stream.closest_arrow(pos) {
+        let mut oscillation = stream.arrow_oscillations[id];
+        oscillation.waveform = oscillation.waveform.next();
+        stream.set_oscillation(id, oscillation);
+    }
+}
+
+/// Multiplies the frequency of the arrow closest to a position.
+pub fn scale_frequency(w: &mut World, pos: [f64; 2], factor: f64) {
+    let stream = &mut w.stream;
+    if let Some(id) = stream.closest_arrow(pos) {
+        let mut oscillation = stream.arrow_oscillations[id];
+        oscillation.frequency *= factor;
+        stream.set_oscillation(id, oscillation);
+    }
+}
+
+pub fn deselect_arrow(w: &mut World) {
+    w.selected_arrow = SelectedArrow(None);
+}
+
+pub fn refresh_moving_arrows(w: &mut World) {
     use settings::stream::SAMPLE_SIZE;
 
-    let stream = unsafe { &mut *current_stream() };
-    let moving_arrows = unsafe { &mut *current_moving_arrows() };
+    let World { ref stream, ref mut moving_arrows, .. } = *w;
     
     moving_arrows.clear();
     let (x, y) = (stream.rect[0], stream.rect[1]);
@@ -154,29 +531,36 @@ pub fn refresh_moving_arrows() {
     }
 }
 
-pub fn update_stream(dt: f64) {
-    use current_stream;
-   
-    unsafe { current_stream() }.update(dt);
+/// Makes the current flow around the rocks of the world.
+pub fn refresh_obstacles(w: &mut World) {
+    let centers = w.rocks.rocks.iter().map(|rock| rock.pos).collect();
+    let radius = w.config.rocks.radius;
+    w.stream.set_obstacles(centers, radius);
 }
 
-pub fn update_moving_arrows(dt: f64) {
-    use current_stream;
-    use current_moving_arrows;
-    use vecmath::vec2_add as add;
-    use vecmath::vec2_scale as scale;
+pub fn update_stream(w: &mut World, dt: f64) {
+    if w.stream.obstacles.radius != w.config.rocks.radius {
+        refresh_obstacles(w);
+    }
+    let phase_vel = w.config.stream.phase_vel;
+    let field_resolution = w.config.stream.field_resolution;
+    w.stream.update(dt, phase_vel, field_resolution);
+}
+
+pub fn update_moving_arrows(w: &mut World, dt: f64) {
+    use vecmath::vec2_sub as sub;
     use vecmath::vec2_len as len;
-    use settings::stream::{ MOVING_ARROW_TIME_SPAN, SPEEDUP };
+    use integrator;
 
-    let stream = unsafe { &mut *current_stream() };
-    let moving_arrows = unsafe { &mut *current_moving_arrows() };
+    let World { ref stream, ref mut moving_arrows, ref config, .. } = *w;
     for moving_arrow in moving_arrows.iter_mut() {
-        let arrow = moving_arrow.arrow;
-        let diff = scale(arrow.dir, dt * SPEEDUP);
-        moving_arrow.arrow.pos = add(arrow.pos, diff);
-        moving_arrow.arrow.dir = stream.at(moving_arrow.arrow.pos);
-        moving_arrow.time += len(diff);
-        if moving_arrow.time > MOVING_ARROW_TIME_SPAN {
+        let pos = moving_arrow.arrow.pos;
+        let next_pos = integrator::advect(config.integrator, pos,
+            dt * config.stream.speedup, |pos| stream.at(pos));
+        moving_arrow.arrow.pos = next_pos;
+        moving_arrow.arrow.dir = stream.at(next_pos);
+        moving_arrow.time += len(sub(next_pos, pos));
+        if moving_arrow.time > config.stream.moving_arrow_time_span {
             // Reset back to beginning.
             let start_pos = moving_arrow.start_pos;
             let dir = stream.at(start_pos);
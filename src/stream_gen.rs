@@ -0,0 +1,208 @@
+//! Fills a stream with arrows from a seed
+//!
+//! The arrows are spread over a sea of `settings::WINDOW_SIZE`,
+//! whatever the size of the window, so a seed gives the same arrows
+//! on every display, headless and when exporting.
+//! Their length is picked from `Stream::strength`.
+//! Their direction follows a general drift,
+//! bent around eddies and wobbled by smooth noise.
+
+use rand::Rand;
+
+use stream::{ Arrow, Oscillation, Stream };
+use world::XorShift;
+
+/// Options for generating a stream.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct StreamGen {
+    /// The same seed gives the same arrows.
+    pub seed: u32,
+    /// How many arrows to add.
+    pub arrows: usize,
+    /// The general direction of the current, its length is ignored.
+    pub drift: [f64; 2],
+    /// How many swirls the current bends around.
+    pub eddies: usize,
+    /// The distance between noise features, zero turns noise off.
+    pub noise_scale: f64,
+}
+
+/// A swirl in the current.
+struct Eddy {
+    pos: [f64; 2],
+    radius: f64,
+    /// 1 for counter clockwise, -1 for clockwise.
+    spin: f64,
+}
+
+/// Random angles at grid corners, interpolated smoothly in between.
+struct Noise {
+    rect: [f64; 4],
+    scale: f64,
+    n: usize,
+    angles: Vec<f64>,
+}
+
+impl Noise {
+    fn new(rect: [f64; 4], scale: f64, rng: &mut XorShift) -> Noise {
+        use std::f64::consts::PI;
+
+        let n = (rect[2].max(rect[3]) / scale).ceil() as usize + 2;
+        Noise {
+            rect: rect,
+            scale: scale,
+            n: n,
+            angles: (0..n * n).map(|_| (random(rng) - 0.5) * PI).collect(),
+        }
+    }
+
+    fn angle(&self, pos: [f64; 2]) -> f64 {
+        let x = ((pos[0] - self.rect[0]) / self.scale).max(0.0);
+        let y = ((pos[1] - self.rect[1]) / self.scale).max(0.0);
+        let i = (x as usize).min(self.n - 2);
+        let j = (y as usize).min(self.n - 2);
+        let tx = smooth(x - i as f64);
+        let ty = smooth(y - j as f64);
+        let at = |i: usize, j: usize| self.angles[j * self.n + i];
+        let top = at(i, j) + (at(i + 1, j) - at(i, j)) * tx;
+        let bottom = at(i, j + 1) + (at(i + 1, j + 1) - at(i, j + 1)) * tx;
+        top + (bottom - top) * ty
+    }
+}
+
+impl StreamGen {
+    /// Checks for values that would take too long to generate.
+    pub fn validate(&self) -> Result<(), String> {
+        use settings::stream::MIN_NOISE_SCALE;
+
+        let scale = self.noise_scale;
+        if !scale.is_finite() || scale < 0.0 || (scale != 0.0 && scale < MIN_NOISE_SCALE) {
+            return Err(format!("`noise_scale` must be zero or at least {}, found `{}`",
+                MIN_NOISE_SCALE, scale));
+        }
+        Ok(())
+    }
+
+    /// Adds arrows to a stream.
+    pub fn generate(&self, stream: &mut Stream) {
+        use vecmath::vec2_add as add;
+        use vecmath::vec2_sub as sub;
+        use vecmath::vec2_len as len;
+        use vecmath::vec2_scale as scale;
+        use vecmath::vec2_normalized as normalized;
+        use vecmath::traits::Radians;
+        use settings::WINDOW_SIZE;
+        use world;
+
+        let mut rng = world::rng(self.seed);
+        let rect = [0.0, 0.0, WINDOW_SIZE[0] as f64, WINDOW_SIZE[1] as f64];
+        let strength = stream.strength;
+        let pick_pos = |rng: &mut XorShift| [
+                rect[0] + random(rng) * rect[2],
+                rect[1] + random(rng) * rect[3]
+            ];
+
+        let drift = if len(self.drift) > 0.0 { normalized(self.drift) } else { [0.0; 2] };
+        let eddy_radius = rect[2].min(rect[3]) / (2.0 * (self.eddies as f64 + 1.0).sqrt());
+        let eddies: Vec<Eddy> = (0..self.eddies).map(|_| Eddy {
+                pos: pick_pos(&mut rng),
+                radius: eddy_radius * (0.5 + random(&mut rng)),
+                spin: if random(&mut rng) < 0.5 { -1.0 } else { 1.0 },
+            }).collect();
+        let noise = if self.noise_scale > 0.0 {
+                Some(Noise::new(rect, self.noise_scale, &mut rng))
+            } else {
+                None
+            };
+
+        let mut arrows = stream.arrows.clone();
+        let mut arrow_phases = stream.arrow_phases.clone();
+        let mut arrow_oscillations = stream.arrow_oscillations.clone();
+        for _ in 0..self.arrows {
+            let pos = pick_pos(&mut rng);
+            let mut dir = drift;
+            for eddy in eddies.iter() {
+                let diff = sub(pos, eddy.pos);
+                let r = len(diff) / eddy.radius;
+                let swirl = 2.0 * (-r * r).exp() * eddy.spin;
+                let tangent = if len(diff) > 0.0 {
+                        normalized([-diff[1], diff[0]])
+                    } else {
+                        [0.0; 2]
+                    };
+                dir = add(dir, scale(tangent, swirl));
+            }
+            if let Some(ref noise) = noise {
+                let (sin, cos) = noise.angle(pos).sin_cos();
+                dir = [dir[0] * cos - dir[1] * sin, dir[0] * sin + dir[1] * cos];
+            }
+            let dir = if len(dir) > 0.0 { normalized(dir) } else { [0.0; 2] };
+            let strength = strength[0] + random(&mut rng) * (strength[1] - strength[0]);
+            arrows.push(Arrow { pos: pos, dir: scale(dir, strength) });
+            arrow_phases.push(random(&mut rng) * <f64 as Radians>::_360());
+            arrow_oscillations.push(Oscillation::new());
+        }
+        stream.set_arrows(arrows, arrow_phases, arrow_oscillations);
+    }
+}
+
+/// A number between 0 and 1.
+fn random(rng: &mut XorShift) -> f64 {
+    <f64 as Rand>::rand(rng)
+}
+
+fn smooth(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use kernel::Kernel;
+    use stream::{ FieldModel, Stream };
+    use super::StreamGen;
+
+    fn generate(seed: u32, rect: [f64; 4]) -> Stream {
+        let mut stream = Stream::new(rect, [10.0, 50.0], Kernel::new(), FieldModel::Average);
+        let gen = StreamGen {
+            seed: seed,
+            arrows: 40,
+            drift: [1.0, 0.3],
+            eddies: 3,
+            noise_scale: 150.0,
+        };
+        gen.generate(&mut stream);
+        stream
+    }
+
+    #[test]
+    fn same_seed() {
+        // Twice the size, like a window on a high density display.
+        let a = generate(7, [0.0, 0.0, 640.0, 480.0]);
+        let b = generate(7, [0.0, 0.0, 1280.0, 960.0]);
+        assert_eq!(a.arrows.len(), 40);
+        assert!(a.arrows == b.arrows);
+        assert!(a.arrow_phases == b.arrow_phases);
+    }
+
+    #[test]
+    fn other_seed() {
+        let a = generate(7, [0.0, 0.0, 640.0, 480.0]);
+        let b = generate(8, [0.0, 0.0, 640.0, 480.0]);
+        assert!(a.arrows != b.arrows);
+    }
+
+    #[test]
+    fn noise_scale() {
+        let gen = |scale| StreamGen {
+            seed: 1,
+            arrows: 1,
+            drift: [1.0, 0.0],
+            eddies: 0,
+            noise_scale: scale,
+        };
+        assert!(gen(0.0).validate().is_ok());
+        assert!(gen(150.0).validate().is_ok());
+        assert!(gen(1e-6).validate().is_err());
+        assert!(gen(-1.0).validate().is_err());
+    }
+}
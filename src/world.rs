@@ -0,0 +1,165 @@
+//! The state of one running game
+
+use rand::Rng;
+
+use blood::Blood;
+use blood_bar::BloodBar;
+use config::Config;
+use editor::Editor;
+use game::GameState;
+use level::Level;
+use palm_trees::PalmTrees;
+use player::Player;
+use rocks::Rocks;
+use sea_birds::SeaBirds;
+use primitives::SelectedPrimitive;
+use stream::{ MovingArrow, SelectedArrow, Stream };
+
+/// Owns everything the simulation reads and writes.
+///
+/// Several worlds can live side by side,
+/// since no system reaches for global state.
+pub struct World {
+    pub stream: Stream,
+    pub moving_arrows: Vec<MovingArrow>,
+    pub player: Player,
+    pub rocks: Rocks,
+    pub selected_arrow: SelectedArrow,
+    pub selected_primitive: SelectedPrimitive,
+    pub game_state: GameState,
+    /// The simulation is paused while the level is edited.
+    pub editing: bool,
+    pub editor: Editor,
+    pub blood_bar: BloodBar,
+    pub blood: Blood,
+    pub palm_trees: PalmTrees,
+    pub sea_birds: SeaBirds,
+    /// The ellipse of land the player must reach.
+    pub beach: [f64; 4],
+    /// Tuning values, which can change while playing.
+    pub config: Config,
+    /// The seed the random generator started with.
+    pub seed: u32,
+    /// Every random decision goes through this generator.
+    pub rng: XorShift,
+}
+
+/// The xorshift generator of `rand`, with a state that can be saved.
+///
+/// Snapshots store a copy of the state,
+/// so taking one does not change the following random decisions.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct XorShift {
+    pub state: [u32; 4],
+}
+
+impl Rng for XorShift {
+    fn next_u32(&mut self) -> u32 {
+        let x = self.state[0];
+        let t = x ^ (x << 11);
+        let w = self.state[3];
+        self.state = [self.state[1], self.state[2], w, w ^ (w >> 19) ^ (t ^ (t >> 8))];
+        self.state[3]
+    }
+}
+
+/// Creates a random generator from a seed.
+pub fn rng(seed: u32) -> XorShift {
+    // Xorshift needs a state that is not all zeros.
+    XorShift { state: [seed, 0x193a6754, 0xa8a7d469, 0x97830e05] }
+}
+
+/// Picks a seed when none is given.
+pub fn random_seed() -> u32 {
+    use rand::{ Rand, thread_rng };
+
+    <u32 as Rand>::rand(&mut thread_rng())
+}
+
+impl World {
+    /// Creates a world from a level, covering a sea rectangle.
+    ///
+    /// Two worlds with the same seed and input make the same decisions.
+    pub fn new(level: &Level, config: Config, rect: [f64; 4], seed: u32) -> World {
+        use kernel::Kernel;
+        use rocks::Rock;
+        use sea_birds::SeaBird;
+        use stream::{ self, Arrow, FieldModel, Oscillation };
+
+        let mut w = World {
+            stream: Stream::new(rect, level.strength,
+                level.kernel.unwrap_or(Kernel::new()),
+                level.model.unwrap_or(FieldModel::Average)),
+            moving_arrows: Vec::new(),
+            player: Player::new(level.start_pos),
+            rocks: Rocks { rocks: Vec::new() },
+            selected_arrow: SelectedArrow(None),
+            selected_primitive: SelectedPrimitive(None),
+            game_state: GameState::Play,
+            editing: false,
+            editor: Editor::new(),
+            blood_bar: BloodBar(level.blood_bar_start),
+            blood: Blood {
+                blood_drops: Vec::new(),
+                time_since_last_drop: 0.0,
+            },
+            palm_trees: PalmTrees { palms: level.palm_trees.clone() },
+            sea_birds: SeaBirds::new(),
+            beach: level.beach,
+            config: config,
+            seed: seed,
+            rng: rng(seed),
+        };
+
+        for arrow in level.arrows.iter() {
+            w.stream.add_arrow(Arrow { pos: arrow.pos, dir: arrow.dir }, arrow.phase,
+                arrow.oscillation.unwrap_or(Oscillation::new()));
+        }
+        if let Some(ref primitives) = level.primitives {
+            w.stream.set_primitives(primitives.clone());
+        }
+        if let Some(ref gen) = level.generate {
+            gen.generate(&mut w.stream);
+        }
+        for &pos in level.rocks.iter() {
+            w.rocks.rocks.push(Rock { pos: pos });
+        }
+        stream::refresh_obstacles(&mut w);
+        stream::refresh_moving_arrows(&mut w);
+        for &pos in level.sea_birds.iter() {
+            let behavior = w.sea_birds.behavior.clone();
+            w.sea_birds.birds.push(SeaBird::new(pos, pos, behavior));
+        }
+        w
+    }
+
+    /// Advances the simulation one time step.
+    ///
+    /// This is the same path the window loop takes on update events,
+    /// so headless runs behave exactly like a played game.
+    pub fn update(&mut self, dt: f64) {
+        use piston::event::{ Event, UpdateArgs };
+        use piston::input::Input;
+        use blood;
+        use blood_bar;
+        use game;
+        use player;
+        use sea_birds;
+        use stream;
+
+        if game::should_update(self) {
+            stream::update_stream(self, dt);
+            stream::update_moving_arrows(self, dt);
+            player::update_player(self, dt);
+            blood_bar::update_blood_bar(self, dt);
+            blood::update_blood(self, dt);
+        }
+
+        game::update_game_state(self);
+
+        if game::should_update(self) {
+            let e: Event<Input> = Event::Update(UpdateArgs { dt: dt });
+            sea_birds::update_sea_birds(self, &e);
+        }
+    }
+}